@@ -0,0 +1,59 @@
+#![feature(test)]
+extern crate lax;
+extern crate test;
+
+use lax::{LoxType,Statement};
+use lax::scanner::Scanner;
+use lax::parser::Parser;
+use lax::resolver::Resolver;
+use lax::interpreter::Interpreter;
+use std::rc::Rc;
+use test::Bencher;
+
+const FIB: &'static str = "
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+
+var result = fib(25);
+";
+
+fn parse(s: &str) -> Vec<Rc<Statement>> {
+    let mut scanner = Scanner::new(s.to_string());
+    scanner.scan().unwrap();
+    let mut parser = Parser::new(scanner.tokens);
+    let ast = parser.parse().unwrap();
+    Resolver::new().resolve(&ast).unwrap();
+    ast
+}
+
+// Calls used to copy the whole interpreter, so their cost grew with every global defined.
+fn interpreter_with_globals(n: usize) -> Interpreter {
+    let i = Interpreter::new();
+    for k in 0..n {
+        i.globals.borrow_mut().define(&format!("global_{}",k),LoxType::Number(k as f64));
+    }
+    i
+}
+
+// A benchmark that stopped computing fib would get much faster without anyone noticing
+fn check(i: &Interpreter) {
+    assert_eq!(i.globals.borrow().get("result"),Some(LoxType::Number(75025.0)));
+}
+
+#[bench]
+fn fib_25(b: &mut Bencher) {
+    let ast = parse(FIB);
+    let mut i = interpreter_with_globals(0);
+    b.iter(|| i.interpret(&ast).unwrap());
+    check(&i);
+}
+
+#[bench]
+fn fib_25_with_10000_globals(b: &mut Bencher) {
+    let ast = parse(FIB);
+    let mut i = interpreter_with_globals(10000);
+    b.iter(|| i.interpret(&ast).unwrap());
+    check(&i);
+}
@@ -1,12 +1,74 @@
 use super::interpreter::Interpreter;
 use super::{LoxError,LoxType};
-use std::fmt::Debug;
+use std::fmt::{self,Debug};
 use std::cmp::Ordering;
 
+// How many arguments a callable takes: anywhere from `min` up to `max`, or any number past
+// `min` when there's no `max`
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>
+}
+
+impl Arity {
+    pub fn exactly(n: usize) -> Arity {
+        Arity { min: n, max: Some(n) }
+    }
+
+    pub fn range(min: usize,max: usize) -> Arity {
+        Arity { min: min, max: Some(max) }
+    }
+
+    pub fn at_least(min: usize) -> Arity {
+        Arity { min: min, max: None }
+    }
+
+    pub fn accepts(&self,n: usize) -> bool {
+        n >= self.min && self.max.map_or(true,|max| n <= max)
+    }
+
+    // What a call that passed `got` arguments is told
+    pub fn mismatch(&self,got: usize) -> String {
+        let noun = match (self.min,self.max) {
+            (1,Some(1)) | (1,None) => "argument",
+            _ => "arguments"
+        };
+        format!("Expected {} {} but got {}",self,noun,got)
+    }
+}
+
+impl From<usize> for Arity {
+    fn from(n: usize) -> Arity {
+        Arity::exactly(n)
+    }
+}
+
+// Reads as the count in "Expected {} arguments"
+impl fmt::Display for Arity {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f,"{}",max),
+            Some(max) => write!(f,"{} to {}",self.min,max),
+            None => write!(f,"at least {}",self.min)
+        }
+    }
+}
+
 pub trait Callable: Debug {
-    fn arity(&self) -> usize;
+    fn arity(&self) -> Arity;
+
+    // What stack traces call this function
+    fn name(&self) -> String {
+        "<native fn>".to_string()
+    }
 
-    fn call(&self, interpreter: &mut Interpreter,arguments: Vec<LoxType>) -> (Interpreter,Result<LoxType,LoxError>);
+    fn call(&self, interpreter: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError>;
+
+    // Methods hand back a copy of themselves with 'this' bound to the instance.
+    fn bind(&self,instance: LoxType) -> Box<Callable> {
+        self.box_clone()
+    }
 
     fn box_clone(&self) -> Box<Callable>;
 }
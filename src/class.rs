@@ -0,0 +1,102 @@
+use super::{Callable,Arity,ErrorKind,LoxError,LoxType,Span};
+use interpreter::Interpreter;
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::fmt;
+
+pub struct LoxClass {
+    pub name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String,Box<Callable>>
+}
+
+impl LoxClass {
+    pub fn new(name: String,superclass: Option<Rc<LoxClass>>,methods: HashMap<String,Box<Callable>>) -> LoxClass {
+        LoxClass {
+            name: name,
+            superclass: superclass,
+            methods: methods
+        }
+    }
+
+    pub fn find_method(&self,name: &str) -> Option<Box<Callable>> {
+        match self.methods.get(name) {
+            Some(m) => Some(m.clone()),
+            None => match self.superclass {
+                Some(ref superclass) => superclass.find_method(name),
+                None => None
+            }
+        }
+    }
+}
+
+// Classes are shared between the environment and every instance they create,
+// so calling one goes through the Rc.
+impl Callable for Rc<LoxClass> {
+    fn arity(&self) -> Arity {
+        match self.find_method("init") {
+            Some(init) => init.arity(),
+            None => Arity::exactly(0)
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn call(&self,i: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+        let instance = LoxType::Instance(Rc::new(RefCell::new(LoxInstance::new(self.clone()))));
+        if let Some(init) = self.find_method("init") {
+            init.bind(instance.clone()).call(i,arguments)?;
+        }
+        Ok(instance)
+    }
+
+    fn box_clone(&self) -> Box<Callable> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"<class {}>",self.name)
+    }
+}
+
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    fields: HashMap<String,LoxType>
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
+        LoxInstance {
+            class: class,
+            fields: HashMap::new()
+        }
+    }
+
+    // Takes the Rc rather than &self so methods can be bound to the instance itself.
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>,name: &str,span: Span) -> Result<LoxType,LoxError> {
+        if let Some(v) = instance.borrow().fields.get(name) {
+            return Ok(v.clone());
+        }
+
+        let method = instance.borrow().class.find_method(name);
+        match method {
+            Some(m) => Ok(LoxType::Callable(m.bind(LoxType::Instance(instance.clone())))),
+            None => Err(LoxError::with_span(format!("Undefined property '{}'",name),span).of_kind(ErrorKind::Runtime))
+        }
+    }
+
+    pub fn set(&mut self,name: &str,value: LoxType) {
+        self.fields.insert(name.to_string(),value);
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"<{} instance>",self.class.name)
+    }
+}
@@ -1,14 +1,15 @@
 use chrono::prelude::*;
-use super::{LoxError,LoxType,Callable};
+use super::{LoxError,LoxType,Callable,Arity};
 use super::interpreter::Interpreter;
 
 #[derive(Debug,PartialEq,PartialOrd,Clone)]
 pub struct Clock;
 
 impl Callable for Clock {
-    fn arity(&self) -> usize { 0 }
-    fn call(&self,interpreter: &mut Interpreter,arguments: Vec<LoxType>) -> (Interpreter,Result<LoxType,LoxError>) {
-        (interpreter.clone(),Ok(LoxType::Number(Local::now().timestamp() as f64)))
+    fn arity(&self) -> Arity { Arity::exactly(0) }
+    fn name(&self) -> String { "clock".to_string() }
+    fn call(&self,interpreter: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+        Ok(LoxType::Number(Local::now().timestamp() as f64))
     }
     fn box_clone(&self) -> Box<Callable> {
         Box::new((*self).clone())
@@ -0,0 +1,151 @@
+use super::{LoxError,ErrorKind,LoxType,quoted};
+use std::collections::HashMap;
+use std::fmt;
+
+// The values that can key a map. Numbers are keyed by their bits, with -0 folded into 0.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub enum MapKey {
+    String(String),
+    Number(u64),
+    Boolean(bool)
+}
+
+impl MapKey {
+    pub fn from_lox(value: &LoxType) -> Result<MapKey,LoxError> {
+        match value {
+            &LoxType::String(ref s) => Ok(MapKey::String(s.clone())),
+            &LoxType::Number(n) if n.is_nan() => Err(LoxError::new("A map key can't be NaN".to_string(),0)),
+            &LoxType::Number(n) => Ok(MapKey::Number(if n == 0.0 { 0.0f64.to_bits() } else { n.to_bits() })),
+            &LoxType::Boolean(b) => Ok(MapKey::Boolean(b)),
+            v => Err(LoxError::new(format!("Map keys must be strings, numbers or booleans but got {}",v.type_name()),0).of_kind(ErrorKind::Type))
+        }
+    }
+
+    pub fn to_lox(&self) -> LoxType {
+        match self {
+            &MapKey::String(ref s) => LoxType::String(s.clone()),
+            &MapKey::Number(bits) => LoxType::Number(f64::from_bits(bits)),
+            &MapKey::Boolean(b) => LoxType::Boolean(b)
+        }
+    }
+}
+
+// Written the way it would be in a map literal
+impl fmt::Display for MapKey {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &MapKey::String(ref s) => write!(f,"{}",quoted(s)),
+            k => write!(f,"{}",k.to_lox())
+        }
+    }
+}
+
+// Keeps its entries in the order they were first inserted
+#[derive(Debug,Clone,Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey,LoxType)>,
+    positions: HashMap<MapKey,usize>
+}
+
+impl LoxMap {
+    pub fn new() -> LoxMap {
+        LoxMap::default()
+    }
+
+    pub fn get(&self,key: &MapKey) -> Option<&LoxType> {
+        self.positions.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn insert(&mut self,key: MapKey,value: LoxType) {
+        match self.positions.get(&key) {
+            Some(&i) => {
+                self.entries[i].1 = value;
+                return;
+            },
+            None => ()
+        }
+        self.positions.insert(key.clone(),self.entries.len());
+        self.entries.push((key,value));
+    }
+
+    pub fn remove(&mut self,key: &MapKey) -> Option<LoxType> {
+        let i = match self.positions.remove(key) {
+            Some(i) => i,
+            None => return None
+        };
+        let (_,value) = self.entries.remove(i);
+        for (n,&(ref k,_)) in self.entries.iter().enumerate().skip(i) {
+            self.positions.insert(k.clone(),n);
+        }
+        Some(value)
+    }
+
+    pub fn contains(&self,key: &MapKey) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn entries(&self) -> &Vec<(MapKey,LoxType)> {
+        &self.entries
+    }
+}
+
+// Indexing shared by both backends. Errors come back without a span for the caller to point
+// at the '['.
+
+pub fn get_index(target: LoxType,index: LoxType) -> Result<LoxType,LoxError> {
+    match target {
+        LoxType::List(ref list) => {
+            let list = list.borrow();
+            let i = list_index(&index,list.len())?;
+            Ok(list[i].clone())
+        },
+        LoxType::Map(ref map) => {
+            let key = MapKey::from_lox(&index)?;
+            match map.borrow().get(&key) {
+                Some(v) => Ok(v.clone()),
+                None => Err(LoxError::new(format!("No key {} in map",key),0))
+            }
+        },
+        t => Err(LoxError::new(format!("Can't index a {}",t.type_name()),0).of_kind(ErrorKind::Type))
+    }
+}
+
+// Hands back the value assigned, same as setting a field
+pub fn set_index(target: LoxType,index: LoxType,value: LoxType) -> Result<LoxType,LoxError> {
+    match target {
+        LoxType::List(ref list) => {
+            let mut list = list.borrow_mut();
+            let i = list_index(&index,list.len())?;
+            list[i] = value.clone();
+            Ok(value)
+        },
+        LoxType::Map(ref map) => {
+            let key = MapKey::from_lox(&index)?;
+            map.borrow_mut().insert(key,value.clone());
+            Ok(value)
+        },
+        t => Err(LoxError::new(format!("Can't assign to an index of a {}",t.type_name()),0).of_kind(ErrorKind::Type))
+    }
+}
+
+// Turns a Lox number into a position in a list of `len` elements. Negative ones count back
+// from the end.
+pub fn list_index(index: &LoxType,len: usize) -> Result<usize,LoxError> {
+    let n = match index {
+        &LoxType::Number(n) => n,
+        i => return Err(LoxError::new(format!("List index must be a number but got {}",i.type_name()),0).of_kind(ErrorKind::Type))
+    };
+    if n.fract() != 0.0 {
+        return Err(LoxError::new(format!("List index must be a whole number but got {}",n),0));
+    }
+
+    let i = if n < 0.0 { n + len as f64 } else { n };
+    if i < 0.0 || i >= len as f64 {
+        return Err(LoxError::new(format!("Index {} is out of range for a list of length {}",n,len),0));
+    }
+    Ok(i as usize)
+}
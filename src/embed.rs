@@ -0,0 +1,187 @@
+use super::*;
+use scanner::Scanner;
+use parser::Parser;
+use resolver::Resolver;
+use interpreter::Interpreter;
+use std::fs::File;
+use std::io::{Read,Write,BufRead};
+use std::path::Path;
+use std::rc::Rc;
+
+// Scans, parses and resolves `source`, collecting every error along the way
+pub fn parse(source: &str) -> Result<Vec<Rc<Statement>>,Vec<LoxError>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let mut errors = match scanner.scan() {
+        Ok(()) => Vec::new(),
+        Err(e) => e
+    };
+
+    // Parse even if scanning failed, so syntax errors get reported in the same run
+    let mut parser = Parser::new(scanner.tokens);
+    let ast = match parser.parse() {
+        Ok(ast) => ast,
+        Err(e) => {
+            errors.extend(e);
+            Vec::new()
+        }
+    };
+
+    if errors.is_empty() {
+        if let Err(e) = Resolver::new().resolve(&ast) {
+            errors.extend(e);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(errors)
+    }
+}
+
+// Runs `statements`, then hands back the value of `result`, all on the backend chosen
+pub fn evaluate(i: &mut Interpreter,statements: &Vec<Rc<Statement>>,result: &Expr,use_vm: bool) -> Result<LoxType,LoxError> {
+    if use_vm {
+        let script = vm::Compiler::new().compile_returning(statements,result)?;
+        vm::evaluate(i,script)
+    } else {
+        i.interpret(statements)?;
+        i.evaluate(result)
+    }
+}
+
+// Everything a host program needs to run Lox: scripts and single expressions go in, values and
+// errors come out, and nothing the script does can bring the host down. Scripts run on a thread
+// of their own with STACK_SIZE of stack, whatever thread the host calls from.
+pub struct Lax {
+    interpreter: Interpreter,
+    use_vm: bool
+}
+
+impl Lax {
+    // Starts out with the standard natives (clock, print, readline) defined
+    pub fn new() -> Lax {
+        let interpreter = Interpreter::new();
+        lox_std::install(&mut interpreter.builtins.borrow_mut());
+        Lax {
+            interpreter: interpreter,
+            use_vm: false
+        }
+    }
+
+    // Runs scripts on the bytecode VM instead of the tree-walker
+    pub fn vm(mut self,use_vm: bool) -> Lax {
+        self.use_vm = use_vm;
+        self
+    }
+
+    // Sends what scripts print to `output` instead of stdout
+    pub fn output<W: Write + 'static>(mut self,output: W) -> Lax {
+        self.interpreter.set_output(output);
+        self
+    }
+
+    // Has readline read from `input` instead of stdin
+    pub fn input<R: BufRead + 'static>(mut self,input: R) -> Lax {
+        self.interpreter.set_input(input);
+        self
+    }
+
+    // Runs `source` and hands back the value of its last statement if that's an expression
+    // (nil otherwise). The trailing ';' can be left off. Only the first error is returned.
+    pub fn eval(&mut self,source: &str) -> Result<LoxType,LoxError> {
+        with_script_stack(|| self.eval_here(source))
+    }
+
+    fn eval_here(&mut self,source: &str) -> Result<LoxType,LoxError> {
+        let trimmed = source.trim_right();
+        // Source ending in '}' is usually a block, but it can be a map
+        let ast = if !trimmed.ends_with(';') {
+            match parse(&format!("{};",trimmed)) {
+                Ok(ast) => ast,
+                Err(_) => parse(source).map_err(first)?
+            }
+        } else {
+            parse(source).map_err(first)?
+        };
+
+        let (last,rest) = match ast.split_last() {
+            Some((last,rest)) => (last.clone(),rest.to_vec()),
+            None => return Ok(LoxType::Nil)
+        };
+
+        match last.as_ref() {
+            &Statement::Expression(ref e) => evaluate(&mut self.interpreter,&rest,e,self.use_vm),
+            _ => {
+                self.execute(&ast)?;
+                Ok(LoxType::Nil)
+            }
+        }
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self,path: P) -> Result<(),LoxError> {
+        let name = path.as_ref().to_string_lossy().into_owned();
+        let mut source = String::new();
+        if let Err(e) = File::open(path.as_ref()).and_then(|mut f| f.read_to_string(&mut source)) {
+            return Err(LoxError::with_lower("File couldn't be read".to_string(),0,e).in_file(&name));
+        }
+
+        with_script_stack(|| {
+            let ast = parse(&source).map_err(|errs| first(errs).in_file(&name))?;
+            self.interpreter.set_file(path.as_ref());
+            self.execute(&ast).map_err(|e| e.in_file(&name))
+        })
+    }
+
+    // Makes a Rust function or closure callable from Lox; see NativeFn for what it can take
+    pub fn register<Args,F: NativeFn<Args>>(&mut self,name: &str,f: F) {
+        self.interpreter.register(name,f);
+    }
+
+    pub fn set_global(&mut self,name: &str,value: LoxType) {
+        self.interpreter.globals.borrow_mut().define(name,value);
+    }
+
+    pub fn get_global(&self,name: &str) -> Option<LoxType> {
+        self.interpreter.globals.borrow().get(name)
+    }
+
+    // Calls a global function or class from the host, checking the arguments like a call in
+    // Lox would
+    pub fn call_function(&mut self,name: &str,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+        let fun: Box<Callable> = match self.get_global(name) {
+            Some(LoxType::Callable(c)) => c,
+            Some(LoxType::Class(c)) => Box::new(c),
+            Some(_) => return Err(LoxError::new(format!("'{}' isn't a function",name),0).of_kind(ErrorKind::Type)),
+            None => return Err(LoxError::new(format!("Undefined function '{}'",name),0).of_kind(ErrorKind::UndefinedVariable))
+        };
+
+        if !fun.arity().accepts(arguments.len()) {
+            return Err(LoxError::new(fun.arity().mismatch(arguments.len()),0).of_kind(ErrorKind::Arity));
+        }
+
+        let interpreter = &mut self.interpreter;
+        with_script_stack(|| interpreter.invoke(fun.as_ref(),arguments,None))
+    }
+
+    // For anything the facade doesn't cover
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    fn execute(&mut self,ast: &Vec<Rc<Statement>>) -> Result<(),LoxError> {
+        if self.use_vm {
+            let script = vm::Compiler::new().compile(ast)?;
+            vm::run(&mut self.interpreter,script)
+        } else {
+            self.interpreter.interpret(ast)
+        }
+    }
+}
+
+fn first(errors: Vec<LoxError>) -> LoxError {
+    match errors.into_iter().next() {
+        Some(e) => e,
+        None => LoxError::new("Unknown error".to_string(),0)
+    }
+}
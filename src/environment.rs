@@ -1,13 +1,15 @@
 use super::{LoxType,Token};
 use std::collections::HashMap;
-use std::boxed::Box;
+use std::rc::Rc;
+use std::cell::RefCell;
 // Kiilll meee
 
-// I don't even know.
+// Frames are shared: closures keep their defining frame alive and see every
+// later assignment made through it.
 #[derive(Debug,Clone)]
 pub struct Environment {
     values: HashMap<String,LoxType>,
-    pub enclosing: Option<Box<Environment>>
+    pub enclosing: Option<Rc<RefCell<Environment>>>
 }
 
 impl Environment {
@@ -18,15 +20,15 @@ impl Environment {
         }
     }
 
-    pub fn with_enclosing(r: Box<Environment>) -> Environment {
+    pub fn with_enclosing(r: Rc<RefCell<Environment>>) -> Environment {
         Environment {
             values: HashMap::new(),
             enclosing: Some(r)
         }
     }
-    
-    pub fn extend(&mut self,other: Box<Environment>) {
-        self.values.extend(other.values);
+
+    pub fn extend(&mut self,other: &Environment) {
+        self.values.extend(other.values.iter().map(|(k,v)| (k.clone(),v.clone())));
     }
 
     pub fn define(&mut self,name: &str,value: LoxType) {
@@ -36,8 +38,8 @@ impl Environment {
     pub fn assign(&mut self,name: &str, value: LoxType) {
         if self.values.contains_key(&name.to_string()) {
             self.define(name,value);
-        } else if let Some(ref mut enclosing) = self.enclosing {
-            enclosing.assign(name, value);
+        } else if let Some(ref enclosing) = self.enclosing {
+            enclosing.borrow_mut().assign(name, value);
         }
     }
 
@@ -47,19 +49,63 @@ impl Environment {
             res.cloned()
         } else {
             if let Some(ref enclosing) = self.enclosing {
-                enclosing.get(&name.to_string())
+                enclosing.borrow().get(&name.to_string())
             } else {
                 None
             }
         }
     }
 
+    // Skips the enclosing frames
+    pub fn get_own(&self,name: &str) -> Option<LoxType> {
+        self.values.get(name).cloned()
+    }
+
+    // This frame's own bindings, sorted by name
+    pub fn entries(&self) -> Vec<(String,LoxType)> {
+        let mut entries = self.values.iter().map(|(k,v)| (k.clone(),v.clone())).collect::<Vec<(String,LoxType)>>();
+        entries.sort_by(|a,b| a.0.cmp(&b.0));
+        entries
+    }
+
+    pub fn ancestor(env: &Rc<RefCell<Environment>>,distance: usize) -> Option<Rc<RefCell<Environment>>> {
+        let mut current = env.clone();
+        for _ in 0..distance {
+            let next = current.borrow().enclosing.clone();
+            match next {
+                Some(e) => current = e,
+                None => return None
+            }
+        }
+        Some(current)
+    }
+
+    pub fn get_at(env: &Rc<RefCell<Environment>>,distance: usize,name: &str) -> Option<LoxType> {
+        match Environment::ancestor(env,distance) {
+            Some(e) => {
+                let value = e.borrow().values.get(name).cloned();
+                value
+            },
+            None => None
+        }
+    }
+
+    pub fn assign_at(env: &Rc<RefCell<Environment>>,distance: usize,name: &str,value: LoxType) -> bool {
+        match Environment::ancestor(env,distance) {
+            Some(e) => {
+                e.borrow_mut().define(name,value);
+                true
+            },
+            None => false
+        }
+    }
+
     pub fn contains(&self,name: &str) -> bool {
         match self.values.contains_key(&name.to_string()) {
             true => true,
             false => {
                 if let Some(ref enclosing) = self.enclosing {
-                    enclosing.contains(&name)
+                    enclosing.borrow().contains(&name)
                 } else {
                     false
                 }
@@ -1,12 +1,105 @@
 use std::io;
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
+use super::{Token,Span,LoxType,LoxClass,LoxInstance};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// One active Lox function when a runtime error was raised, innermost first
+#[derive(Debug,Clone,PartialEq)]
+pub struct TraceFrame {
+    pub function: String,
+    pub line: i32
+}
+
+// What went wrong, broadly. The first three come from checking the source before it runs, the
+// rest from running it.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum ErrorKind {
+    Lexical,
+    // Includes source the bytecode compiler can't fit into a chunk
+    Syntax,
+    Resolution,
+    // A value of the wrong type for an operation or a native's parameter
+    Type,
+    Arity,
+    UndefinedVariable,
+    Io,
+    Import,
+    // Anything else raised while running: bad indices, missing properties, stack overflow
+    Runtime,
+    // A value thrown by the script and never caught
+    Thrown,
+    // A backend finding its own state broken, like the VM's stack running out under it. A bug
+    // in Lax rather than the script, so try can't catch it.
+    Internal
+}
+
+impl ErrorKind {
+    // Stable across releases, for tools that match on errors rather than messages
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ErrorKind::Lexical => "E001",
+            ErrorKind::Syntax => "E002",
+            ErrorKind::Resolution => "E003",
+            ErrorKind::Type => "E004",
+            ErrorKind::Arity => "E005",
+            ErrorKind::UndefinedVariable => "E006",
+            ErrorKind::Io => "E007",
+            ErrorKind::Import => "E008",
+            ErrorKind::Runtime => "E009",
+            ErrorKind::Thrown => "E010",
+            ErrorKind::Internal => "E011"
+        }
+    }
+}
+
+// Why an import failed. The error carrying one points at the import statement; what went wrong
+// inside the module, if anything, comes with the module's source so it can be shown in context.
+#[derive(Debug)]
+pub enum ImportError {
+    // Not next to the importing file or anywhere on the search path
+    NotFound,
+    Unreadable(PathBuf,io::Error),
+    // The modules involved, starting and ending with the one imported twice
+    Cycle(Vec<PathBuf>),
+    // Scanning, parsing or resolving the module failed
+    Parse(PathBuf,String,Vec<LoxError>),
+    // Running its top level failed
+    Runtime(PathBuf,String,Box<LoxError>)
+}
+
+impl ImportError {
+    // The module's own errors and the source they point into
+    fn inner(&self) -> Option<(&str,Vec<&LoxError>)> {
+        match self {
+            &ImportError::Parse(_,ref source,ref errs) => Some((source,errs.iter().collect())),
+            &ImportError::Runtime(_,ref source,ref e) => Some((source,vec![e.as_ref()])),
+            _ => None
+        }
+    }
+}
+
+// What some errors carry besides a message. Boxed together: errors are passed around far more
+// often than either is looked at, and every byte on LoxError is paid for on each native frame.
+#[derive(Debug)]
+enum Payload {
+    Import(ImportError),
+    Thrown(LoxType)
+}
 
 #[derive(Debug)]
 pub struct LoxError {
+    kind: ErrorKind,
     line: i32,
     err: String,
-    lower: Option<io::Error>
+    lower: Option<io::Error>,
+    span: Option<Span>,
+    file: Option<String>,
+    backtrace: Vec<TraceFrame>,
+    payload: Option<Box<Payload>>
 }
 
 impl Error for LoxError {
@@ -16,33 +109,273 @@ impl Error for LoxError {
 
     fn cause(&self) -> Option<&Error> {
         if let Some(ref err) = self.lower {
-            Some(err as &Error)
-        } else {
-            None
+            return Some(err as &Error);
+        }
+
+        match self.import_error() {
+            Some(&ImportError::Unreadable(_,ref err)) => Some(err as &Error),
+            Some(&ImportError::Runtime(_,_,ref err)) => Some(err.as_ref() as &Error),
+            _ => None
         }
     }
 }
 
 impl fmt::Display for LoxError {
     fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f,"Error: {} in line {}",&self.err,&self.line)
+        write!(f,"Error[{}]: {} in line {}",self.kind.code(),&self.err,&self.line)?;
+        if let Some(ref span) = self.span {
+            write!(f,", column {}",span.column)?;
+        }
+        if let Some(ref file) = self.file {
+            write!(f," of {}",file)?;
+        }
+        writeln!(f,"")?;
+        for line in self.trace_lines() {
+            writeln!(f,"{}",line)?;
+        }
+        if let Some((_,errs)) = self.import_error().and_then(|i| i.inner()) {
+            for e in errs {
+                write!(f,"{}",e)?;
+            }
+        }
+        Ok(())
     }
 }
 
 impl LoxError {
     pub fn new(s: String,l: i32) -> LoxError {
         LoxError {
+            kind: ErrorKind::Runtime,
             line: l,
             err: s,
-            lower: None
+            lower: None,
+            span: None,
+            file: None,
+            backtrace: Vec::new(),
+            payload: None
         }
     }
 
     pub fn with_lower(s: String,l: i32,e: io::Error) -> LoxError {
         LoxError {
+            kind: ErrorKind::Io,
             line: l,
             err: s,
-            lower: Some(e)
+            lower: Some(e),
+            span: None,
+            file: None,
+            backtrace: Vec::new(),
+            payload: None
+        }
+    }
+
+    pub fn with_span(s: String,span: Span) -> LoxError {
+        LoxError {
+            kind: ErrorKind::Runtime,
+            line: span.line,
+            err: s,
+            lower: None,
+            span: Some(span),
+            file: None,
+            backtrace: Vec::new(),
+            payload: None
+        }
+    }
+
+    pub fn at(s: String,t: &Token) -> LoxError {
+        LoxError::with_span(s,t.span)
+    }
+
+    // `name` is the module as the import statement `t` spells it
+    pub fn import(import: ImportError,name: &str,t: &Token) -> LoxError {
+        let message = match import {
+            ImportError::NotFound => format!("Couldn't find module '{}'",name),
+            ImportError::Unreadable(..) => format!("Couldn't read module '{}'",name),
+            ImportError::Cycle(ref modules) => {
+                let modules = modules.iter().map(|p| p.display().to_string()).collect::<Vec<String>>();
+                format!("Import cycle: {}",modules.join(" -> "))
+            },
+            ImportError::Parse(..) => format!("Module '{}' has errors",name),
+            ImportError::Runtime(..) => format!("Module '{}' failed while running",name)
+        };
+        let mut e = LoxError::at(message,t).of_kind(ErrorKind::Import);
+        e.payload = Some(Box::new(Payload::Import(import)));
+        e
+    }
+
+    // What `throw` raises. The message is the value itself, or its message field if it has one,
+    // so rethrowing a caught runtime error still reads the same when nothing catches it.
+    pub fn throw(value: LoxType,span: Span) -> LoxError {
+        let message = match value {
+            LoxType::Instance(ref i) => match LoxInstance::get(i,"message",span) {
+                Ok(LoxType::String(s)) => s,
+                _ => format!("{}",value)
+            },
+            _ => format!("{}",value)
+        };
+        let mut e = LoxError::with_span(message,span).of_kind(ErrorKind::Thrown);
+        e.payload = Some(Box::new(Payload::Thrown(value)));
+        e
+    }
+
+    // Errors start out as Runtime (or Io, with an io::Error underneath) until they're told
+    // otherwise
+    pub fn of_kind(mut self,kind: ErrorKind) -> LoxError {
+        self.kind = kind;
+        self
+    }
+
+    // Points errors raised without a location (failed casts and the like) at the given span
+    pub fn located(mut self,span: Span) -> LoxError {
+        if self.span.is_none() {
+            self.line = span.line;
+            self.span = Some(span);
+        }
+        self
+    }
+
+    pub fn in_file(mut self,file: &str) -> LoxError {
+        if self.file.is_none() {
+            self.file = Some(file.to_string());
+        }
+        self
+    }
+
+    // Only the innermost call boundary an error crosses knows the whole stack
+    pub fn traced(mut self,backtrace: Vec<TraceFrame>) -> LoxError {
+        if self.backtrace.is_empty() {
+            self.backtrace = backtrace;
+        }
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.err
+    }
+
+    pub fn line(&self) -> i32 {
+        self.line
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_ref().map(|f| f.as_str())
+    }
+
+    pub fn backtrace(&self) -> &[TraceFrame] {
+        &self.backtrace
+    }
+
+    pub fn import_error(&self) -> Option<&ImportError> {
+        match self.payload.as_ref().map(|p| p.as_ref()) {
+            Some(&Payload::Import(ref i)) => Some(i),
+            _ => None
+        }
+    }
+
+    // Whether a try can catch it: everything the script can cause
+    pub fn catchable(&self) -> bool {
+        self.kind != ErrorKind::Internal
+    }
+
+    pub fn thrown(&self) -> Option<&LoxType> {
+        match self.payload.as_ref().map(|p| p.as_ref()) {
+            Some(&Payload::Thrown(ref v)) => Some(v),
+            _ => None
+        }
+    }
+
+    // What a catch clause binds: a thrown value as it was thrown, anything else as an Error
+    // instance with the message, line and code.
+    pub fn into_value(self) -> LoxType {
+        if let Some(payload) = self.payload {
+            if let Payload::Thrown(value) = *payload {
+                return value;
+            }
+        }
+        let class = Rc::new(LoxClass::new("Error".to_string(),None,HashMap::new()));
+        let mut error = LoxInstance::new(class);
+        error.set("message",LoxType::String(self.err));
+        error.set("line",LoxType::Number(self.line as f64));
+        error.set("code",LoxType::String(self.kind.code().to_string()));
+        LoxType::Instance(Rc::new(RefCell::new(error)))
+    }
+
+    // rustc-style report: the message, where it happened, the offending line with a caret
+    // underline and the Lox backtrace. `source` has to be the text of the file the error came from.
+    pub fn render(&self,source: &str) -> String {
+        let mut out = format!("error[{}]: {}\n",self.kind.code(),&self.err);
+        out.push_str(&self.snippet(source));
+        for line in self.trace_lines() {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        if let Some((source,errs)) = self.import_error().and_then(|i| i.inner()) {
+            for e in errs {
+                out.push_str(&e.render(source));
+            }
+        }
+        out
+    }
+
+    // Runaway recursion would print thousands of identical frames, so the middle gets cut
+    fn trace_lines(&self) -> Vec<String> {
+        const SHOWN: usize = 10;
+        let line = |frame: &TraceFrame| format!("    at {} (line {})",frame.function,frame.line);
+
+        if self.backtrace.len() <= SHOWN * 2 {
+            return self.backtrace.iter().map(line).collect();
         }
+
+        let mut lines = self.backtrace[..SHOWN].iter().map(&line).collect::<Vec<String>>();
+        lines.push(format!("    ... {} more",self.backtrace.len() - SHOWN * 2));
+        lines.extend(self.backtrace[self.backtrace.len() - SHOWN..].iter().map(&line));
+        lines
+    }
+
+    fn snippet(&self,source: &str) -> String {
+        let file = match self.file {
+            Some(ref f) => f.as_str(),
+            None => "<input>"
+        };
+
+        let span = match self.span {
+            Some(span) => span,
+            None => return format!(" --> {}:{}\n",file,self.line)
+        };
+
+        let text = match source.lines().nth((span.line as usize).saturating_sub(1)) {
+            Some(text) => text,
+            None => return format!(" --> {}:{}:{}\n",file,span.line,span.column)
+        };
+
+        let gutter = format!("{}",span.line).len();
+        let padding = " ".repeat(gutter);
+        let width = source.get(span.offset..span.offset + span.length)
+            .map(|s| s.chars().take_while(|c| *c != '\n').count())
+            .unwrap_or(1);
+        let width = if width == 0 { 1 } else { width };
+
+        // Tabs are widened the same way in the line and under it so the caret lines up
+        let indent = text.chars().take(span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { "    " } else { " " })
+            .collect::<String>();
+
+        let mut out = format!("{}--> {}:{}:{}\n",padding,file,span.line,span.column);
+        out.push_str(&format!("{} |\n",padding));
+        out.push_str(&format!("{} | {}\n",span.line,text.replace("\t","    ")));
+        out.push_str(&format!("{} | {}{}\n",padding,indent,"^".repeat(width)));
+        out
     }
 }
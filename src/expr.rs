@@ -1,6 +1,11 @@
-use super::{Token,LoxType};
+use super::{Token,LoxType,Statement};
 use std::rc::Rc;
+use std::cell::Cell;
 use std::fmt;
+use std::mem;
+
+// How many scopes up a local lives, filled in by the resolver. None means global.
+pub type Depth = Cell<Option<usize>>;
 
 #[derive(Clone,Debug)]
 pub enum Expr {
@@ -8,10 +13,86 @@ pub enum Expr {
     Grouping(Rc<Expr>),
     Literal(LoxType),
     Unary(Token,Rc<Expr>),
-    Variable(Token),
-    Assign(Token,Rc<Expr>),
+    Variable(Token,Depth),
+    Assign(Token,Rc<Expr>,Depth),
     Logical(Rc<Expr>,Token,Rc<Expr>),
-    Call(Rc<Expr>,Token,Vec<Rc<Expr>>)
+    Call(Rc<Expr>,Token,Vec<Rc<Expr>>),
+    Get(Rc<Expr>,Token),
+    Set(Rc<Expr>,Token,Rc<Expr>),
+    This(Token,Depth),
+    Super(Token,Token,Depth),
+    // The tokens are the opening bracket or brace
+    List(Token,Vec<Rc<Expr>>),
+    Map(Token,Vec<(Rc<Expr>,Rc<Expr>)>),
+    Index(Rc<Expr>,Token,Rc<Expr>),
+    SetIndex(Rc<Expr>,Token,Rc<Expr>,Rc<Expr>),
+    // `fun (x) { ... }` or `(x) => ...`, holding a Statement::Function named <anonymous>
+    Lambda(Rc<Statement>)
+}
+
+impl Expr {
+    // The token errors about this expression should point at
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            &Expr::Binary(_,ref t,_) | &Expr::Unary(ref t,_) | &Expr::Variable(ref t,_) |
+            &Expr::Assign(ref t,_,_) | &Expr::Logical(_,ref t,_) | &Expr::Call(_,ref t,_) |
+            &Expr::Get(_,ref t) | &Expr::Set(_,ref t,_) | &Expr::This(ref t,_) |
+            &Expr::Super(_,ref t,_) | &Expr::List(ref t,_) | &Expr::Map(ref t,_) | &Expr::Index(_,ref t,_) |
+            &Expr::SetIndex(_,ref t,_,_) => Some(t),
+            &Expr::Grouping(ref e) => e.token(),
+            &Expr::Lambda(ref declaration) => match declaration.as_ref() {
+                &Statement::Function(ref t,_,_) => Some(t),
+                _ => None
+            },
+            &Expr::Literal(_) => None
+        }
+    }
+
+    // The operand a chain continues from, left as nil here
+    fn take_left(&mut self) -> Option<Rc<Expr>> {
+        match self {
+            &mut Expr::Binary(ref mut left,_,_) | &mut Expr::Logical(ref mut left,_,_) |
+            &mut Expr::Call(ref mut left,_,_) | &mut Expr::Get(ref mut left,_) |
+            &mut Expr::Index(ref mut left,_,_) => Some(mem::replace(left,Rc::new(Expr::Literal(LoxType::Nil)))),
+            _ => None
+        }
+    }
+
+    // A chain of binary operators like a + b - c, or of logical ones like a and b or c, as its
+    // leftmost operand followed by each operator and the operand to its right. The parser
+    // builds chains as trees as deep as they are long, so later passes walk them with this
+    // instead of recursing once per operator.
+    pub fn chain(&self) -> (&Expr,Vec<(&Token,&Expr)>) {
+        let mut links = Vec::new();
+        let mut e = self;
+        loop {
+            match (self,e) {
+                (&Expr::Binary(..),&Expr::Binary(ref left,ref op,ref right)) |
+                (&Expr::Logical(..),&Expr::Logical(ref left,ref op,ref right)) => {
+                    links.push((op,right.as_ref()));
+                    e = left;
+                },
+                _ => break
+            }
+        }
+        links.reverse();
+        (e,links)
+    }
+}
+
+// Chains of operators and postfix operators build trees as deep as they are long, and dropping
+// those node by node would recurse once per link. Their leftmost spine is taken apart in a
+// loop instead.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut next = self.take_left();
+        while let Some(e) = next {
+            next = match Rc::try_unwrap(e) {
+                Ok(mut e) => e.take_left(),
+                Err(_) => None
+            };
+        }
+    }
 }
 
 impl fmt::Display for Expr {
@@ -29,10 +110,10 @@ impl fmt::Display for Expr {
             &Expr::Unary(ref token,ref e) => {
                 write!(f,"({} {})",&token.lexeme,e)
             },
-            &Expr::Variable(ref token) => {
+            &Expr::Variable(ref token,_) => {
                 write!(f,"var({})",&token.lexeme)
             },
-            &Expr::Assign(ref token,ref e) => {
+            &Expr::Assign(ref token,ref e,_) => {
                 write!(f,"{} = {}",&token.lexeme,e)
             },
             &Expr::Logical(ref left,ref token,ref right) => {
@@ -40,12 +121,57 @@ impl fmt::Display for Expr {
             },
             &Expr::Call(ref calle,_,ref args) => {
                 write!(f,"{}(",calle)?;
-                for a in args {
-                    write!(f,"{},",a)?;
-                }
+                write_list(f,args)?;
                 write!(f,")")
+            },
+            &Expr::Get(ref object,ref name) => {
+                write!(f,"{}.{}",object,&name.lexeme)
+            },
+            &Expr::Set(ref object,ref name,ref value) => {
+                write!(f,"{}.{} = {}",object,&name.lexeme,value)
+            },
+            &Expr::This(_,_) => {
+                write!(f,"this")
+            },
+            &Expr::Super(_,ref method,_) => {
+                write!(f,"super.{}",&method.lexeme)
+            },
+            &Expr::List(_,ref elements) => {
+                write!(f,"[")?;
+                write_list(f,elements)?;
+                write!(f,"]")
+            },
+            &Expr::Map(_,ref entries) => {
+                write!(f,"{{")?;
+                for (i,&(ref k,ref v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f,", ")?;
+                    }
+                    write!(f,"{}: {}",k,v)?;
+                }
+                write!(f,"}}")
+            },
+            &Expr::Index(ref object,_,ref index) => {
+                write!(f,"{}[{}]",object,index)
+            },
+            &Expr::SetIndex(ref object,_,ref index,ref value) => {
+                write!(f,"{}[{}] = {}",object,index,value)
+            },
+            &Expr::Lambda(ref declaration) => match declaration.as_ref() {
+                &Statement::Function(ref t,_,_) => write!(f,"<fn {}>",t.lexeme),
+                _ => write!(f,"<fn>")
             }
         }
     }
 }
 
+fn write_list(f: &mut fmt::Formatter,exprs: &Vec<Rc<Expr>>) -> fmt::Result {
+    for (i,e) in exprs.iter().enumerate() {
+        if i > 0 {
+            write!(f,", ")?;
+        }
+        write!(f,"{}",e)?;
+    }
+    Ok(())
+}
+
@@ -1,70 +1,112 @@
-use super::{Callable,LoxError,Statement,LoxType,Environment};
-use interpreter::{InterpreterError,Interpreter};
+use super::{Callable,Arity,LoxError,Statement,LoxType,Environment};
+use interpreter::{Interpreter,Flow};
 use std::rc::Rc;
+use std::cell::RefCell;
 use std::boxed::Box;
 use std::fmt;
-use std::borrow::Borrow;
+use std::path::PathBuf;
 
 #[derive(Clone)]
 pub struct LoxFun {
-    declaration: Rc<Statement>
+    declaration: Rc<Statement>,
+    closure: Rc<RefCell<Environment>>,
+    // The globals of the module it was declared in, which it keeps seeing wherever it's called
+    // from, and the module's file, which errors raised in it point into
+    globals: Rc<RefCell<Environment>>,
+    file: Option<Rc<PathBuf>>,
+    initializer: bool
 }
 
 impl LoxFun {
-    pub fn new(declaration: Rc<Statement>) -> LoxFun {
+    pub fn new(declaration: Rc<Statement>,closure: Rc<RefCell<Environment>>,globals: Rc<RefCell<Environment>>,file: Option<Rc<PathBuf>>) -> LoxFun {
+        LoxFun::method(declaration,closure,globals,file,false)
+    }
+
+    pub fn method(declaration: Rc<Statement>,closure: Rc<RefCell<Environment>>,globals: Rc<RefCell<Environment>>,file: Option<Rc<PathBuf>>,initializer: bool) -> LoxFun {
         LoxFun {
-            declaration: declaration
+            declaration: declaration,
+            closure: closure,
+            globals: globals,
+            file: file,
+            initializer: initializer
         }
     }
-}
 
-impl Callable for LoxFun {
-    fn arity(&self) -> usize {
-        match self.declaration.borrow() {
-            &Statement::Function(_,ref args,_) => args.len(),
-            _ => 0
+    fn run(&self,i: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+        let env = Rc::new(RefCell::new(Environment::with_enclosing(self.closure.clone())));
+
+        let (params,body) = match self.declaration.as_ref() {
+            &Statement::Function(_,ref params,ref body) => (params,body),
+            _ => return Err(LoxError::new("Tried to call an invalid function".to_string(),0))
+        };
+
+        let statements = match body.as_ref() {
+            &Statement::Block(ref statements) => statements,
+            _ => return Err(LoxError::new("Tried to call an invalid function".to_string(),0))
+        };
+
+        let mut arguments = arguments.into_iter();
+        for param in params {
+            let value = match (arguments.next(),&param.default) {
+                (Some(arg),_) => arg,
+                (None,&Some(ref default)) => i.evaluate_in(default,env.clone())?,
+                (None,&None) => LoxType::Nil
+            };
+            env.borrow_mut().define(param.name.lexeme.as_str(),value);
         }
-    }
 
-    fn call(&self,i: &mut Interpreter,arguments: Vec<LoxType>) -> (Interpreter, Result<LoxType,LoxError>) {
-        let mut env = Environment::with_enclosing(i.env.clone());
+        match i.interpret_block(statements,Some(env))? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(LoxType::Nil)
+        }
+    }
+}
 
-        let (args,statements) = match self.declaration.borrow() {
-            &Statement::Function(_,ref args,ref statements) => {
-                (args,statements)
+impl Callable for LoxFun {
+    fn arity(&self) -> Arity {
+        match self.declaration.as_ref() {
+            &Statement::Function(_,ref params,_) => {
+                let required = params.iter().filter(|p| p.default.is_none()).count();
+                Arity::range(required,params.len())
             },
-            _ => panic!("Tryed to call an invalid function")
-        };
+            _ => Arity::exactly(0)
+        }
+    }
 
-        for (n,arg) in arguments.into_iter().enumerate() {
-            env.define(args[n].lexeme.as_str(),arg);
+    fn name(&self) -> String {
+        match self.declaration.as_ref() {
+            &Statement::Function(ref t,_,_) => t.lexeme.clone(),
+            _ => "<invalid function>".to_string()
         }
+    }
 
-        let statements = if let &Statement::Function(_,_,ref block_statement) = self.declaration.borrow() {
-            if let &Statement::Block(ref statements_) = block_statement.borrow() {
-                statements_.clone()
-            } else {
-                panic!("Tried to call an invalid function");
-            }
-        } else {
-            panic!("Tried to call an invalid function")
-        };
+    fn call(&self,i: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+        // Errors leaving a function called from another file say which file they point into
+        let foreign = self.file != i.file();
+        let res = i.within(self.globals.clone(),self.file.clone(),|i| self.run(i,arguments)).map_err(|e| match self.file {
+            Some(ref file) if foreign => e.in_file(&file.display().to_string()),
+            _ => e
+        });
 
-        let res = i.interpret_block(&statements,Some(env));
-        //println!("got res{:?}",&res);
+        // init() always hands back the instance, even on a bare 'return;'
         match res {
-            Ok(_) => (i.clone(),Ok(LoxType::Nil)),
-            Err(e) => {
-            //    println!("err {:?}",e);
-                match e {
-                    InterpreterError::LoxError(e_) => (i.clone(),Err(e_)),
-                    InterpreterError::Return(v) => (i.clone(),Ok(v)),
-                    _ => panic!("what") // user friendly messages, everyone
-                }
-            }
+            Ok(_) if self.initializer => Ok(self.closure.borrow().get("this").unwrap_or(LoxType::Nil)),
+            res => res
         }
     }
 
+    fn bind(&self,instance: LoxType) -> Box<Callable> {
+        let mut env = Environment::with_enclosing(self.closure.clone());
+        env.define("this",instance);
+        Box::new(LoxFun {
+            declaration: self.declaration.clone(),
+            closure: Rc::new(RefCell::new(env)),
+            globals: self.globals.clone(),
+            file: self.file.clone(),
+            initializer: self.initializer
+        })
+    }
+
     fn box_clone(&self) -> Box<Callable> {
         Box::new((*self).clone())
     }
@@ -72,7 +114,7 @@ impl Callable for LoxFun {
 
 impl fmt::Debug for LoxFun {
     fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
-        match self.declaration.borrow() {
+        match self.declaration.as_ref() {
             &Statement::Function(ref t,_,_) => write!(f,"<function {}>",t.lexeme),
             _ => write!(f,"Invalid function")
         }
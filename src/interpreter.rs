@@ -1,153 +1,452 @@
 use super::*;
-use scanner::*;
-use parser::*;
+use embed::parse;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self,Read,Write,BufRead};
+use std::env;
+use std::path::{Path,PathBuf};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::convert::{TryInto,TryFrom};
-use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::boxed::Box;
 use std::mem;
-// This is... weird
+use std::collections::HashMap;
+// How a statement finished: by running to its end, or by jumping out of the code around it.
+// Errors travel separately, as the Err side, so neither can be mistaken for the other.
 #[derive(Debug)]
-pub enum InterpreterError {
-    LoxError(LoxError),
-    LoxErrors(Vec<LoxError>),
-    Return(LoxType)
+pub enum Flow {
+    Next,
+    Return(LoxType),
+    // Out to the innermost loop; the parser only allows them inside one
+    Break,
+    Continue
 }
 
-impl From<LoxError> for InterpreterError {
-    fn from(v: LoxError) -> InterpreterError {
-        InterpreterError::LoxError(v)
-    }
+pub struct Interpreter {
+    pub env: Rc<RefCell<Environment>>,
+    pub globals: Rc<RefCell<Environment>>,
+    // Natives, which the script's globals and every module's sit on top of
+    pub builtins: Rc<RefCell<Environment>>,
+    // Functions being run and the line each one was called from, outermost first
+    call_stack: Vec<TraceFrame>,
+    // How many calls are in progress, and how many may be
+    depth: usize,
+    depth_limit: usize,
+    // The file being run, which imports are looked up next to, and where else to look
+    file: Option<Rc<PathBuf>>,
+    search_path: Vec<PathBuf>,
+    // Modules that have run, by canonical path, and the ones whose top level is still running
+    modules: HashMap<PathBuf,Rc<LoxModule>>,
+    loading: Vec<PathBuf>,
+    // Where print writes and readline reads. Without an input of its own, lines come straight
+    // out of stdin's buffer, so the REPL and readline take turns on the one reader.
+    output: Box<Write>,
+    input: Option<Box<BufRead>>
 }
 
-impl From<Vec<LoxError>> for InterpreterError {
-    fn from(v: Vec<LoxError>) -> InterpreterError {
-        InterpreterError::LoxErrors(v)
-    }
-}
-
-#[derive(Clone)]
-pub struct Interpreter { pub env: Box<Environment> }
-
 impl Interpreter {
     pub fn new() -> Interpreter {
+        let builtins = Rc::new(RefCell::new(Environment::new()));
+        let globals = Rc::new(RefCell::new(Environment::with_enclosing(builtins.clone())));
         Interpreter {
-            env: Box::new(Environment::new())
+            env: globals.clone(),
+            globals: globals,
+            builtins: builtins,
+            call_stack: Vec::new(),
+            depth: 0,
+            depth_limit: MAX_DEPTH,
+            file: None,
+            search_path: env::var_os("LAX_PATH").map_or(Vec::new(),|p| env::split_paths(&p).collect()),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            output: Box::new(io::stdout()),
+            input: None
         }
     }
 
-    pub fn import(&mut self,file: Token) -> Result<(),InterpreterError> {
-        let mut s = String::new();
-        match File::open(String::try_from(file.literal.unwrap()).unwrap()) {
-            Ok(mut k) => k.read_to_string(&mut s),
-            Err(e) => return Err(InterpreterError::LoxError(LoxError::with_lower("Imported file couldn't be opened".to_string(),file.line,e)))
-        };
+    // The script about to run, so its imports are found next to it rather than in the working
+    // directory
+    pub fn set_file<P: AsRef<Path>>(&mut self,path: P) {
+        self.file = Some(Rc::new(path.as_ref().to_path_buf()));
+    }
+
+    // Directories searched for imports that aren't next to the importing file. Starts out as
+    // LAX_PATH.
+    pub fn set_search_path(&mut self,paths: Vec<PathBuf>) {
+        self.search_path = paths;
+    }
+
+    // Where scripts' output goes instead of stdout: a file, a socket, a buffer the host shares
+    pub fn set_output<W: Write + 'static>(&mut self,output: W) {
+        self.output = Box::new(output);
+    }
+
+    // Where readline reads from instead of stdin
+    pub fn set_input<R: BufRead + 'static>(&mut self,input: R) {
+        self.input = Some(Box::new(input));
+    }
+
+    // For natives that do I/O, so they go wherever the host pointed the interpreter
+    pub fn output(&mut self) -> &mut Write {
+        &mut *self.output
+    }
+
+    // The next line of input, newline included. Stdin is only locked while a line is read.
+    pub fn read_line(&mut self,line: &mut String) -> io::Result<usize> {
+        match self.input {
+            Some(ref mut input) => input.read_line(line),
+            None => io::stdin().read_line(line)
+        }
+    }
 
-        let env = {
-            let mut scanner = Scanner::new(s);
-            scanner.scan()?;
-            let mut parser = Parser::new(scanner.tokens);
-            let ast = parser.parse()?;
-            let mut i = Interpreter::new();
-            i.interpret(&ast);
-            i.env
+    // How many calls may be in progress at once (MAX_DEPTH unless set). The thread running
+    // scripts needs enough stack for that many, which STACK_SIZE is for MAX_DEPTH.
+    pub fn set_depth_limit(&mut self,depth: usize) {
+        self.depth_limit = depth;
+    }
+
+    // Makes a Rust function or closure callable from Lox under `name`
+    pub fn register<Args,F: NativeFn<Args>>(&mut self,name: &str,f: F) {
+        self.builtins.borrow_mut().define(name,LoxType::Callable(Box::new(Native::new(name,f))));
+    }
+
+    // Every call on either backend is counted here, and false once there are too many in
+    // progress. Each one that got in has to leave().
+    pub fn enter(&mut self) -> bool {
+        if self.depth >= self.depth_limit {
+            return false;
+        }
+        self.depth += 1;
+        true
+    }
+
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    // Calls go through here on both backends so runtime errors come out with a backtrace.
+    // Errors that don't say where they happened (from natives, mostly) get the call site, if
+    // the call came from a script rather than the host.
+    pub fn invoke(&mut self,fun: &Callable,arguments: Vec<LoxType>,call_site: Option<Span>) -> Result<LoxType,LoxError> {
+        let line = call_site.map_or(0,|s| s.line);
+        if !self.enter() {
+            let backtrace = self.backtrace(line);
+            let msg = "Stack overflow".to_string();
+            let e = match call_site {
+                Some(span) => LoxError::with_span(msg,span),
+                None => LoxError::new(msg,line)
+            };
+            return Err(e.traced(backtrace));
+        }
+        self.call_stack.push(TraceFrame { function: fun.name(), line: line });
+        let result = fun.call(self,arguments).map_err(|e| {
+            let e = match call_site {
+                Some(span) => e.located(span),
+                None => e
+            };
+            if e.backtrace().is_empty() {
+                let backtrace = self.backtrace(e.line());
+                e.traced(backtrace)
+            } else {
+                e
+            }
+        });
+        self.call_stack.pop();
+        self.leave();
+        result
+    }
+
+    // Each frame is reported at the line it was executing: the innermost one where the error
+    // happened, the rest where they called the next one.
+    fn backtrace(&self,line: i32) -> Vec<TraceFrame> {
+        let mut frames = Vec::new();
+        let mut line = line;
+        for frame in self.call_stack.iter().rev() {
+            frames.push(TraceFrame { function: frame.function.clone(), line: line });
+            line = frame.line;
+        }
+        frames.push(TraceFrame { function: "<script>".to_string(), line: line });
+        frames
+    }
+
+    fn lookup_variable(&self,name: &Token,depth: &Depth) -> Result<LoxType,LoxError> {
+        let value = match depth.get() {
+            Some(d) => Environment::get_at(&self.env,d,&name.lexeme),
+            None => self.globals.borrow().get(&name.lexeme)
         };
 
-        self.env.extend(env);
+        match value {
+            Some(r) => Ok(r),
+            None => Err(LoxError::at("Variable not found".to_string(),name).of_kind(ErrorKind::UndefinedVariable))
+        }
+    }
+
+    pub fn import(&mut self,file: &Token,imports: &Imports) -> Result<(),LoxError> {
+        let module = self.load(file)?;
+        match imports {
+            // The resolver can't know these names, so they're globals wherever the import is
+            &Imports::All => for (name,value) in module.exports() {
+                self.globals.borrow_mut().define(&name,value);
+            },
+            &Imports::Module(ref name) => self.env.borrow_mut().define(&name.lexeme,LoxType::Module(module.clone())),
+            // Nothing is bound unless every name is there
+            &Imports::Names(ref names) => {
+                let mut values = Vec::new();
+                for name in names {
+                    values.push(module.get(&name.lexeme,name.span)?);
+                }
+                for (name,value) in names.iter().zip(values) {
+                    self.env.borrow_mut().define(&name.lexeme,value);
+                }
+            }
+        }
         Ok(())
     }
 
-    pub fn interpret_block(&mut self,statements: &Vec<Rc<Statement>>, environment: Option<Environment>) -> Result<(),InterpreterError> {
+    // Runs the module `file` names the first time it's imported; after that it comes from the
+    // cache. A module's globals sit on top of the natives, apart from the importer's.
+    pub fn load(&mut self,file: &Token) -> Result<Rc<LoxModule>,LoxError> {
+        let name = match file.literal {
+            Some(LoxType::String(ref name)) => name.clone(),
+            _ => return Err(LoxError::at("Expected a file name to import".to_string(),file).of_kind(ErrorKind::Import))
+        };
+
+        let path = match resolve_import(&name,self.file.as_ref().map(|f| f.as_path()),&self.search_path) {
+            Some(path) => path,
+            None => return Err(LoxError::import(ImportError::NotFound,&name,file))
+        };
+
+        if let Some(module) = self.modules.get(&path) {
+            return Ok(module.clone());
+        }
+
+        if let Some(start) = self.loading.iter().position(|p| *p == path) {
+            let cycle = self.loading[start..].iter().cloned().chain(Some(path)).collect();
+            return Err(LoxError::import(ImportError::Cycle(cycle),&name,file));
+        }
+
+        let mut source = String::new();
+        if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut source)) {
+            return Err(LoxError::import(ImportError::Unreadable(path,e),&name,file));
+        }
+
+        // Errors inside the module point into the module's source, not ours
+        let shown = path.display().to_string();
+        let ast = match parse(&source) {
+            Ok(ast) => ast,
+            Err(errs) => {
+                let errs = errs.into_iter().map(|e| e.in_file(&shown)).collect();
+                return Err(LoxError::import(ImportError::Parse(path,source,errs),&name,file));
+            }
+        };
+
+        let globals = Rc::new(RefCell::new(Environment::with_enclosing(self.builtins.clone())));
+        self.loading.push(path.clone());
+        let res = self.within(globals.clone(),Some(Rc::new(path.clone())),|i| i.interpret_block(&ast,Some(globals.clone())));
+        self.loading.pop();
+
+        if let Err(e) = res {
+            return Err(LoxError::import(ImportError::Runtime(path,source,Box::new(e.in_file(&shown))),&name,file));
+        }
+
+        let module = Rc::new(LoxModule::new(&name,path.clone(),globals));
+        self.modules.insert(path,module.clone());
+        Ok(module)
+    }
+
+    // Runs `f` as code from `file` that sees `globals`, the way a module's top level and its
+    // functions run
+    pub fn within<T,F: FnOnce(&mut Interpreter) -> T>(&mut self,globals: Rc<RefCell<Environment>>,file: Option<Rc<PathBuf>>,f: F) -> T {
+        let previous_globals = mem::replace(&mut self.globals,globals);
+        let previous_file = mem::replace(&mut self.file,file);
+        let res = f(self);
+        self.globals = previous_globals;
+        self.file = previous_file;
+        res
+    }
+
+    // The file the running code came from, if the host said
+    pub fn file(&self) -> Option<Rc<PathBuf>> {
+        self.file.clone()
+    }
+
+    pub fn interpret_block(&mut self,statements: &Vec<Rc<Statement>>, environment: Option<Rc<RefCell<Environment>>>) -> Result<Flow,LoxError> {
     	let new = match environment {
-            Some(e) => Box::new(Environment::with_enclosing(Box::new(e))),
-            None => Box::new(Environment::with_enclosing(self.env.clone()))
+            Some(e) => e,
+            None => Rc::new(RefCell::new(Environment::with_enclosing(self.env.clone())))
         };
-        self.env = new;
-        let res = self.interpret(statements);
-    	self.env = self.env.clone().enclosing.unwrap();
+        let previous = mem::replace(&mut self.env,new);
+        let res = self.execute(statements);
+    	self.env = previous;
         res
     }
 
-    pub fn interpret(&mut self,statements: &Vec<Rc<Statement>>) -> Result<(),InterpreterError> {
-        'interpreter: for s in statements {
-            match s.borrow() {
+    // Runs a script's top level, where there's nothing left to jump out to
+    pub fn interpret(&mut self,statements: &Vec<Rc<Statement>>) -> Result<(),LoxError> {
+        self.execute(statements).map(|_| ())
+    }
+
+    fn execute(&mut self,statements: &Vec<Rc<Statement>>) -> Result<Flow,LoxError> {
+        for s in statements {
+            match s.as_ref() {
                 &Statement::Expression(ref e) => { self.evaluate(&e)?; },
                 &Statement::Variable(ref name,ref init) => {
                     let value = match init {
                         &Some(ref i) => self.evaluate(i)?,
                         &None => LoxType::Nil
                     };
-                    self.env.define(&name.lexeme,value);
+                    self.env.borrow_mut().define(&name.lexeme,value);
                 },
-                &Statement::Block(ref statements) => {
-                    self.interpret_block(statements,None)?;
+                &Statement::Block(ref statements) => match self.interpret_block(statements,None)? {
+                    Flow::Next => (),
+                    flow => return Ok(flow)
                 },
                 &Statement::If(ref cond,ref then,ref or) => {
-                    if bool::from(self.evaluate(cond)?) {
-                        self.interpret(&vec![then.clone()])?;
-                    } else if let &Some(ref or_branch) = or {
-                        self.interpret(&vec![or_branch.clone()])?;
+                    let branch = if bool::from(self.evaluate(cond)?) {
+                        Some(then.clone())
+                    } else {
+                        or.clone()
+                    };
+                    if let Some(branch) = branch {
+                        match self.execute(&vec![branch])? {
+                            Flow::Next => (),
+                            flow => return Ok(flow)
+                        }
                     }
                 },
-                &Statement::While(ref cond,ref body) => {
+                &Statement::While(ref cond,ref body,ref increment) => {
                     while bool::from(self.evaluate(cond)?) {
-                        self.interpret(&vec![body.clone()])?;
+                        match self.execute(&vec![body.clone()])? {
+                            Flow::Next | Flow::Continue => (),
+                            Flow::Break => break,
+                            flow => return Ok(flow)
+                        }
+                        if let &Some(ref i) = increment {
+                            self.evaluate(i)?;
+                        }
+                    }
+                },
+                &Statement::Break(_) => return Ok(Flow::Break),
+                &Statement::Continue(_) => return Ok(Flow::Continue),
+                &Statement::Throw(ref keyword,ref e) => {
+                    let value = self.evaluate(e)?;
+                    return Err(LoxError::throw(value,keyword.span));
+                },
+                &Statement::Try(ref body,ref catch,ref finally) => {
+                    let mut result = self.execute(&vec![body.clone()]);
+                    if result.as_ref().err().map_or(false,|e| !e.catchable()) {
+                        return result;
+                    }
+                    if let &Some(ref c) = catch {
+                        if let Err(e) = result {
+                            let mut env = Environment::with_enclosing(self.env.clone());
+                            env.define(&c.name.lexeme,e.into_value());
+                            result = self.interpret_block(&vec![c.body.clone()],Some(Rc::new(RefCell::new(env))));
+                        }
+                    }
+                    // The parser keeps jumps out of finally, so it either finishes or fails
+                    if let &Some(ref f) = finally {
+                        self.execute(&vec![f.clone()])?;
+                    }
+                    match result? {
+                        Flow::Next => (),
+                        flow => return Ok(flow)
                     }
                 },
                 &Statement::Function(ref t,_,_) => {
-                    let fun = LoxFun::new(s.clone());
-                    self.env.define(&t.lexeme,LoxType::Callable(Box::new(fun)))
+                    let fun = LoxFun::new(s.clone(),self.env.clone(),self.globals.clone(),self.file.clone());
+                    self.env.borrow_mut().define(&t.lexeme,LoxType::Callable(Box::new(fun)))
                 },
                 &Statement::Return(_,ref exp) => {
                 //    println!("returning");
-                    return Err(InterpreterError::Return(self.evaluate(exp)?));
+                    return Ok(Flow::Return(self.evaluate(exp)?));
                 },
-                &Statement::Import(ref t) => {
-                    self.import(t.clone())?;
+                &Statement::Import(ref t,ref imports) => {
+                    self.import(t,imports)?;
+                },
+                &Statement::Class(ref name,ref superclass,ref methods) => {
+                    let superclass = match superclass {
+                        &Some(ref e) => match self.evaluate(e)? {
+                            LoxType::Class(c) => Some(c),
+                            _ => return Err(LoxError::at("Superclass must be a class".to_string(),name).of_kind(ErrorKind::Type))
+                        },
+                        &None => None
+                    };
+
+                    // Methods of a subclass close over an extra frame holding 'super'
+                    let closure = match superclass {
+                        Some(ref c) => {
+                            let mut env = Environment::with_enclosing(self.env.clone());
+                            env.define("super",LoxType::Class(c.clone()));
+                            Rc::new(RefCell::new(env))
+                        },
+                        None => self.env.clone()
+                    };
+
+                    let mut table: HashMap<String,Box<Callable>> = HashMap::new();
+                    for m in methods {
+                        if let &Statement::Function(ref t,_,_) = m.as_ref() {
+                            table.insert(t.lexeme.clone(),Box::new(LoxFun::method(m.clone(),closure.clone(),self.globals.clone(),self.file.clone(),t.lexeme == "init")));
+                        }
+                    }
+
+                    let class = LoxClass::new(name.lexeme.clone(),superclass,table);
+                    self.env.borrow_mut().define(&name.lexeme,LoxType::Class(Rc::new(class)));
                 }
             }
         }
-        Ok(())
+        Ok(Flow::Next)
+    }
+
+    // Evaluates `e` as if it appeared in `environment`
+    pub fn evaluate_in(&mut self,e: &Expr,environment: Rc<RefCell<Environment>>) -> Result<LoxType,LoxError> {
+        let previous = mem::replace(&mut self.env,environment);
+        let res = self.evaluate(e);
+        self.env = previous;
+        res
     }
 
-    pub fn evaluate(&mut self,e: &Expr) -> Result<LoxType,InterpreterError> {
+    pub fn evaluate(&mut self,e: &Expr) -> Result<LoxType,LoxError> {
         match e {
-            &Expr::Assign(ref t,ref v) => {
+            &Expr::Assign(ref t,ref v,ref depth) => {
                 let value = self.evaluate(v)?;
-                if self.env.contains(&t.lexeme) {
-                    self.env.assign(&t.lexeme,value);
+                let assigned = match depth.get() {
+                    Some(d) => Environment::assign_at(&self.env,d,&t.lexeme,value),
+                    None => {
+                        let mut globals = self.globals.borrow_mut();
+                        if globals.contains(&t.lexeme) {
+                            globals.assign(&t.lexeme,value);
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                };
+
+                if assigned {
                     return Ok((LoxType::Nil));
                 } else {
-                    return Err((InterpreterError::LoxError(LoxError::new("Undefined variable".to_string(),t.line.clone()))));
+                    return Err(LoxError::at("Undefined variable".to_string(),t).of_kind(ErrorKind::UndefinedVariable));
                 }
             },
-            &Expr::Variable(ref t) => {
-                match self.env.get(&t.lexeme) {
-                    Some(r) => return Ok((r)),
-                    None => return Err((InterpreterError::LoxError(LoxError::new("Variable not found".to_string(),t.line.clone()))))
-                }
-            },
-            &Expr::Logical(ref left,ref op,ref right) => {
-                let left = self.evaluate(left)?;
-                if op.token == TokenType::Or {
-                    if bool::from(left.clone()) {
-                        return Ok((left))
-                    }
-                } else {
-                    if !bool::from(left.clone()) {
-                        return Ok((left))
+            &Expr::Variable(ref t,ref depth) => self.lookup_variable(t,depth),
+            // Each operator in the chain applies to the value of everything before it
+            &Expr::Logical(..) => {
+                let (first,links) = e.chain();
+                let mut left = self.evaluate(first)?;
+                for (op,right) in links {
+                    let truthy = bool::from(left.clone());
+                    if (op.token == TokenType::Or) != truthy {
+                        left = self.evaluate(right)?;
                     }
                 }
-
-                self.evaluate(right)
+                Ok(left)
             },
             &Expr::Call(ref callee,ref paren,ref args) => {
-                let fun = match self.evaluate(callee)? {
+                let fun: Box<Callable> = match self.evaluate(callee)? {
                     LoxType::Callable(c) => c,
-                    _ => return Err((InterpreterError::LoxError(LoxError::new("Expected callable object but got normal type".to_string(),paren.line.clone()))))
+                    LoxType::Class(c) => Box::new(c),
+                    _ => return Err(LoxError::at("Expected callable object but got normal type".to_string(),paren).of_kind(ErrorKind::Type))
                 };
 
                 let mut arguments: Vec<LoxType> = Vec::new();
@@ -155,84 +454,167 @@ impl Interpreter {
                     arguments.push(self.evaluate(a)?);
                 }
 
-                if arguments.len() != fun.arity() {
-                    return Err((InterpreterError::LoxError(LoxError::new(format!("Expected {} arguments but got {}",fun.arity(),arguments.len()),paren.line.clone()))));
+                if !fun.arity().accepts(arguments.len()) {
+                    return Err(LoxError::at(fun.arity().mismatch(arguments.len()),paren).of_kind(ErrorKind::Arity));
                 }
 
-                let (i,res) = fun.call(self,arguments);
-                mem::replace(self,i);
-                Ok(res?)
+                Ok(self.invoke(fun.as_ref(),arguments,Some(paren.span))?)
             }
+            &Expr::Get(ref object,ref name) => {
+                match self.evaluate(object)? {
+                    LoxType::Instance(ref instance) => Ok(LoxInstance::get(instance,&name.lexeme,name.span)?),
+                    LoxType::Module(ref module) => Ok(module.get(&name.lexeme,name.span)?),
+                    _ => Err(LoxError::at("Only instances have properties".to_string(),name).of_kind(ErrorKind::Type))
+                }
+            },
+            &Expr::Set(ref object,ref name,ref value) => {
+                match self.evaluate(object)? {
+                    LoxType::Instance(ref instance) => {
+                        let value = self.evaluate(value)?;
+                        instance.borrow_mut().set(&name.lexeme,value.clone());
+                        Ok(value)
+                    },
+                    _ => Err(LoxError::at("Only instances have fields".to_string(),name).of_kind(ErrorKind::Type))
+                }
+            },
+            &Expr::List(_,ref elements) => {
+                let mut values = Vec::new();
+                for e in elements {
+                    values.push(self.evaluate(e)?);
+                }
+                Ok(LoxType::list(values))
+            },
+            &Expr::Map(ref brace,ref entries) => {
+                let mut values = Vec::new();
+                for &(ref k,ref v) in entries {
+                    values.push((self.evaluate(k)?,self.evaluate(v)?));
+                }
+
+                // Keys are checked once everything's evaluated, same as on the VM
+                let mut map = LoxMap::new();
+                for (key,value) in values {
+                    let key = MapKey::from_lox(&key).map_err(|e| e.located(brace.span))?;
+                    map.insert(key,value);
+                }
+                Ok(LoxType::map(map))
+            },
+            &Expr::Index(ref object,ref bracket,ref index) => {
+                let target = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                Ok(get_index(target,index).map_err(|e| e.located(bracket.span))?)
+            },
+            &Expr::SetIndex(ref object,ref bracket,ref index,ref value) => {
+                let target = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+                Ok(set_index(target,index,value).map_err(|e| e.located(bracket.span))?)
+            },
+            &Expr::Lambda(ref declaration) => {
+                Ok(LoxType::Callable(Box::new(LoxFun::new(declaration.clone(),self.env.clone(),self.globals.clone(),self.file.clone()))))
+            },
+            &Expr::This(ref keyword,ref depth) => self.lookup_variable(keyword,depth),
+            &Expr::Super(ref keyword,ref method,ref depth) => {
+                // 'this' always sits in the frame right below 'super'
+                let lookup = match depth.get() {
+                    Some(d) if d > 0 => (Environment::get_at(&self.env,d,"super"),Environment::get_at(&self.env,d - 1,"this")),
+                    _ => (None,None)
+                };
+                let (superclass,this) = match lookup {
+                    (Some(LoxType::Class(c)),Some(this)) => (c,this),
+                    _ => return Err(LoxError::at("Can't use 'super' outside of a subclass".to_string(),keyword))
+                };
+
+                match superclass.find_method(&method.lexeme) {
+                    Some(m) => Ok(LoxType::Callable(m.bind(this))),
+                    None => Err(LoxError::at(format!("Undefined property '{}'",&method.lexeme),method).of_kind(ErrorKind::Runtime))
+                }
+            },
             &Expr::Literal(ref t) => Ok(t.clone()), // the easy one
             &Expr::Grouping(ref expr) => self.evaluate(expr),
             &Expr::Unary(ref op,ref expr) => {
-                let right = self.evaluate(e)?;
+                let right = self.evaluate(expr)?;
                 match op.token {
-                    TokenType::Minus => {
-                        return Ok((LoxType::Number((-(f64::try_from(right)?)).clone())));
-                    },
-                    TokenType::Bang => {
-                        return Ok((LoxType::Boolean((!(bool::try_from(right)?)).clone())));
+                    TokenType::Minus => match right {
+                        LoxType::Number(n) => Ok(LoxType::Number(-n)),
+                        _ => Err(LoxError::at("Operand must be a number.".to_string(),op).of_kind(ErrorKind::Type))
                     },
-                    _ => return Err((InterpreterError::LoxError(LoxError::new("Invalid unary operator".to_string(),0))))
+                    // Anything but nil and false is true, same as in a condition
+                    TokenType::Bang => Ok(LoxType::Boolean(!bool::from(right))),
+                    _ => Err(LoxError::at("Invalid unary operator".to_string(),op).of_kind(ErrorKind::Internal))
                 }
             },
-            &Expr::Binary(ref left,ref op,ref right) => {
-                let left = self.evaluate(left)?;
-                let right = self.evaluate(right)?;
-
-                match op.token.clone() {
-                    TokenType::Minus => {
-                        let result = f64::try_from(left)?.clone() - f64::try_from(right)?.clone();
-                        return Ok((LoxType::Number(result)));
-                    },
-                    TokenType::Slash => {
-                        let result = f64::try_from(left)?.clone() / f64::try_from(right)?.clone();
-                        return Ok((LoxType::Number(result)));
-                    },
-                    TokenType::Star => {
-                        let result = f64::try_from(left)?.clone() * f64::try_from(right)?.clone();
-                        return Ok((LoxType::Number(result)));
-                    },
-                    TokenType::Plus => {
-                        match (&left,&right) {
-                            (&LoxType::Number(_),&LoxType::Number(_)) => {
-                                let result = f64::try_from(left)?.clone() + f64::try_from(right)?.clone();
-                                return Ok((LoxType::Number(result)));
-                            },
-                            (&LoxType::String(_),&LoxType::String(_)) => {
-                                return Ok((LoxType::String(format!("{}{}",left,right))));
-                            },
-                            _ => {
-                                return Err((InterpreterError::LoxError(LoxError::new("Can't add two diferent types".to_string(),0))));
-                            }
-                        }
-                    },
-                    TokenType::Greater => {
-                        let result = left > right;
-                        return Ok((LoxType::Boolean(result)));
-                    },
-                    TokenType::GreaterEqual => {
-                        let result =  left  >= right;
-                        return Ok((LoxType::Boolean(result)));
-                    },
-                    TokenType::Less => {
-                        let result = left  < right;
-                        return Ok((LoxType::Boolean(result)));
-                    },
-                    TokenType::LessEqual => {
-                        let result = left  <= right;
-                        return Ok((LoxType::Boolean(result)));
-                    },
-                    TokenType::BangEqual => {
-                        return Ok((LoxType::Boolean(left != right)));
-                    },
-                    TokenType::EqualEqual => {
-                        return Ok((LoxType::Boolean(left == right)));
-                    },
-                    _ => return Err((InterpreterError::LoxError(LoxError::new("Invalid binary operator".to_string(),0))))
-                };
+            &Expr::Binary(..) => {
+                let (first,links) = e.chain();
+                let mut left = self.evaluate(first)?;
+                for (op,right) in links {
+                    let right = self.evaluate(right)?;
+                    left = binary(op,left,right).map_err(|e| e.located(op.span))?;
+                }
+                Ok(left)
             }
         }
     }
 }
+
+// Both sides of an arithmetic operator, which only works on numbers
+fn numbers(op: &Token,left: LoxType,right: LoxType) -> Result<(f64,f64),LoxError> {
+    match (left,right) {
+        (LoxType::Number(l),LoxType::Number(r)) => Ok((l,r)),
+        _ => Err(LoxError::at("Operands must be numbers.".to_string(),op).of_kind(ErrorKind::Type))
+    }
+}
+
+// Operands are already evaluated; casts that fail get pointed at the operator by the caller
+fn binary(op: &Token,left: LoxType,right: LoxType) -> Result<LoxType,LoxError> {
+    match op.token.clone() {
+        TokenType::Minus => {
+            let (left,right) = numbers(op,left,right)?;
+            return Ok((LoxType::Number(left - right)));
+        },
+        TokenType::Slash => {
+            let (left,right) = numbers(op,left,right)?;
+            return Ok((LoxType::Number(left / right)));
+        },
+        TokenType::Star => {
+            let (left,right) = numbers(op,left,right)?;
+            return Ok((LoxType::Number(left * right)));
+        },
+        TokenType::Plus => {
+            match (&left,&right) {
+                (&LoxType::Number(_),&LoxType::Number(_)) => {
+                    let result = f64::try_from(left)?.clone() + f64::try_from(right)?.clone();
+                    return Ok((LoxType::Number(result)));
+                },
+                (&LoxType::String(_),_) | (_,&LoxType::String(_)) => {
+                    return Ok((LoxType::String(format!("{}{}",left,right))));
+                },
+                _ => {
+                    return Err(LoxError::at("Can't add two different types".to_string(),op).of_kind(ErrorKind::Type));
+                }
+            }
+        },
+        TokenType::Greater => {
+            let result = left.ordering(&right)? == Some(Ordering::Greater);
+            return Ok((LoxType::Boolean(result)));
+        },
+        TokenType::GreaterEqual => {
+            let result = match left.ordering(&right)? { Some(Ordering::Greater) | Some(Ordering::Equal) => true, _ => false };
+            return Ok((LoxType::Boolean(result)));
+        },
+        TokenType::Less => {
+            let result = left.ordering(&right)? == Some(Ordering::Less);
+            return Ok((LoxType::Boolean(result)));
+        },
+        TokenType::LessEqual => {
+            let result = match left.ordering(&right)? { Some(Ordering::Less) | Some(Ordering::Equal) => true, _ => false };
+            return Ok((LoxType::Boolean(result)));
+        },
+        TokenType::BangEqual => {
+            return Ok((LoxType::Boolean(left != right)));
+        },
+        TokenType::EqualEqual => {
+            return Ok((LoxType::Boolean(left == right)));
+        },
+        _ => return Err(LoxError::at("Invalid binary operator".to_string(),op).of_kind(ErrorKind::Internal))
+    }
+}
@@ -15,6 +15,16 @@ mod environment;
 mod callable;
 mod clock;
 mod fun;
+mod class;
+mod limits;
+mod native;
+mod collections;
+mod module;
+pub use class::*;
+pub use limits::*;
+pub use native::*;
+pub use collections::*;
+pub use module::*;
 pub use fun::*; 
 pub use clock::*;
 pub use callable::*;
@@ -28,5 +38,10 @@ pub use token::*;
 pub mod lox_std;
 pub mod scanner;
 pub mod parser;
+pub mod resolver;
 pub mod interpreter;
+pub mod vm;
+pub mod repl;
+pub mod embed;
+pub use embed::Lax;
 // TRIPLE THREAT
@@ -0,0 +1,47 @@
+use std::mem;
+use std::panic;
+use std::thread;
+
+// Recursion in the parser, the resolver, the compiler and the interpreter is bounded by
+// counting, so the same script hits the same limit on either backend and in any build. What
+// changes between builds is only how much stack that takes.
+
+// How deeply statements and expressions may nest in source
+pub const MAX_NESTING: usize = 48;
+
+// How many calls may be in progress at once
+pub const MAX_DEPTH: usize = 512;
+
+// Stack for MAX_DEPTH calls, each made from as deep as MAX_NESTING lets source go. A level
+// costs over ten times more in a debug build than in a release one.
+#[cfg(debug_assertions)]
+pub const STACK_SIZE: usize = 1024 * 1024 * 1024;
+#[cfg(not(debug_assertions))]
+pub const STACK_SIZE: usize = 128 * 1024 * 1024;
+
+// Runs `f` on a thread with STACK_SIZE of stack and waits for it, like a scoped thread would.
+// The interpreter is full of Rc, so it can't be sent anywhere. That's fine here: the calling
+// thread is blocked until `f` is done, so nothing is ever touched from two threads at once.
+pub fn with_script_stack<T,F: FnOnce() -> T>(f: F) -> T {
+    let mut f = Some(f);
+    let mut result = None;
+    let run = unbound(Box::new(|| result = f.take().map(|f| f())));
+    let spawned = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        let mut run = run;
+        run()
+    });
+
+    match spawned.map(|t| t.join()) {
+        Ok(Ok(())) => (),
+        Ok(Err(panic)) => panic::resume_unwind(panic),
+        // Without a thread of its own, `f` runs with whatever stack this one has
+        Err(_) => return (f.take().unwrap())()
+    }
+    result.unwrap()
+}
+
+// Lets with_script_stack() hand a borrowing closure to thread::spawn. Only sound because it
+// joins the thread before anything the closure borrows goes away.
+fn unbound<'a>(run: Box<FnMut() + 'a>) -> Box<FnMut() + Send> {
+    unsafe { mem::transmute(run) }
+}
@@ -1,7 +1,13 @@
-use super::super::{LoxType,LoxError,Callable};
+use super::super::{LoxType,LoxError,ErrorKind,Callable,Arity,Rest,LoxMap,MapKey,list_index};
 use interpreter::Interpreter;
-use std::io::{self,Read};
+use std::io::Write;
 use std::boxed::Box;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+type List = Rc<RefCell<Vec<LoxType>>>;
+type Map = Rc<RefCell<LoxMap>>;
 
 macro_rules! callable_fn {
     ($fn:expr,$name:tt,$arity:expr) => {
@@ -9,11 +15,15 @@ macro_rules! callable_fn {
             pub struct $name;
 
             impl Callable for $name {
-                fn arity(&self) -> usize {
+                fn arity(&self) -> Arity {
                     $arity
                 }
 
-                fn call(&self,i: &mut Interpreter,arguments: Vec<LoxType>) -> (Interpreter,Result<LoxType,LoxError>) {
+                fn name(&self) -> String {
+                    stringify!($name).to_lowercase()
+                }
+
+                fn call(&self,i: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
                     $fn(i,arguments)
                 }
 
@@ -24,13 +34,101 @@ macro_rules! callable_fn {
     }
 }
 
+// Prints its arguments separated by spaces, to the interpreter's output
 callable_fn!(|i: &mut Interpreter,args: Vec<LoxType>| {
-    println!("{}",args[0]);
-    (i.clone(),Ok(LoxType::Nil))
-},Print,1);
+    let line: Vec<String> = args.iter().map(|a| format!("{}",a)).collect();
+    writeln!(i.output(),"{}",line.join(" ")).map_err(|e| LoxError::with_lower("Couldn't write the output".to_string(),0,e))?;
+    Ok(LoxType::Nil)
+},Print,Arity::at_least(1));
 
+// Reads a line from the interpreter's input, newline included; "" at the end of it
 callable_fn!(|i: &mut Interpreter,args| {
     let mut s = String::new();
-    io::stdin().read_line(&mut s).unwrap();
-    (i.clone(),Ok(LoxType::String(s)))
-},Readline,0);
+    i.read_line(&mut s).map_err(|e| LoxError::with_lower("Couldn't read the input".to_string(),0,e))?;
+    Ok(LoxType::String(s))
+},Readline,Arity::exactly(0));
+
+pub fn max(first: f64,rest: Rest<f64>) -> f64 {
+    rest.0.into_iter().fold(first,f64::max)
+}
+
+pub fn min(first: f64,rest: Rest<f64>) -> f64 {
+    rest.0.into_iter().fold(first,f64::min)
+}
+
+pub fn len(value: LoxType) -> Result<f64,LoxError> {
+    match value {
+        LoxType::List(ref l) => Ok(l.borrow().len() as f64),
+        LoxType::Map(ref m) => Ok(m.borrow().len() as f64),
+        LoxType::String(ref s) => Ok(s.chars().count() as f64),
+        v => Err(LoxError::new(format!("Can't take the length of a {}",v.type_name()),0).of_kind(ErrorKind::Type))
+    }
+}
+
+pub fn push(list: List,values: Rest<LoxType>) {
+    list.borrow_mut().extend(values.0);
+}
+
+pub fn pop(list: List) -> Result<LoxType,LoxError> {
+    list.borrow_mut().pop().ok_or_else(|| LoxError::new("Can't pop from an empty list".to_string(),0))
+}
+
+// The index can be one past the end, which appends
+pub fn insert(list: List,index: f64,value: LoxType) -> Result<(),LoxError> {
+    let mut list = list.borrow_mut();
+    let i = if index == list.len() as f64 { list.len() } else { list_index(&LoxType::Number(index),list.len())? };
+    list.insert(i,value);
+    Ok(())
+}
+
+pub fn remove(list: List,index: f64) -> Result<LoxType,LoxError> {
+    let mut list = list.borrow_mut();
+    let len = list.len();
+    let i = list_index(&LoxType::Number(index),len)?;
+    Ok(list.remove(i))
+}
+
+// A new list of the elements from `start` up to but not including `end` (the end of the list
+// if left off). Negative bounds count back from the end and out of range ones are clamped.
+pub fn slice(list: List,start: f64,end: Option<f64>) -> Vec<LoxType> {
+    let list = list.borrow();
+    let len = list.len() as f64;
+    let bound = |n: f64| {
+        let n = if n < 0.0 { n + len } else { n };
+        n.max(0.0).min(len) as usize
+    };
+    let (start,end) = (bound(start),bound(end.unwrap_or(len)));
+    if start >= end {
+        return Vec::new();
+    }
+    list[start..end].to_vec()
+}
+
+// Sorts in place. Every element has to be a number, or every element a string.
+pub fn sort(list: List) -> Result<(),LoxError> {
+    let mut list = list.borrow_mut();
+    let numbers = list.iter().all(|v| v.type_name() == "number");
+    let strings = list.iter().all(|v| v.type_name() == "string");
+    if !numbers && !strings {
+        return Err(LoxError::new("Can only sort a list of numbers or a list of strings".to_string(),0).of_kind(ErrorKind::Type));
+    }
+    list.sort_by(|a,b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Ok(())
+}
+
+pub fn keys(map: Map) -> Vec<LoxType> {
+    map.borrow().entries().iter().map(|&(ref k,_)| k.to_lox()).collect()
+}
+
+pub fn values(map: Map) -> Vec<LoxType> {
+    map.borrow().entries().iter().map(|&(_,ref v)| v.clone()).collect()
+}
+
+pub fn has(map: Map,key: LoxType) -> Result<bool,LoxError> {
+    Ok(map.borrow().contains(&MapKey::from_lox(&key)?))
+}
+
+// Hands back the value that was removed, or nil if the key wasn't there
+pub fn delete(map: Map,key: LoxType) -> Result<Option<LoxType>,LoxError> {
+    Ok(map.borrow_mut().remove(&MapKey::from_lox(&key)?))
+}
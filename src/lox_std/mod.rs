@@ -1,2 +1,23 @@
 mod functions;
 pub use self::functions::*;
+use super::{LoxType,Environment,Clock,Native};
+
+// Defines the natives every script can count on
+pub fn install(env: &mut Environment) {
+    env.define("clock",LoxType::Callable(Box::new(Clock)));
+    env.define("print",LoxType::Callable(Box::new(Print)));
+    env.define("readline",LoxType::Callable(Box::new(Readline)));
+    env.define("max",LoxType::Callable(Box::new(Native::new("max",max))));
+    env.define("min",LoxType::Callable(Box::new(Native::new("min",min))));
+    env.define("len",LoxType::Callable(Box::new(Native::new("len",len))));
+    env.define("push",LoxType::Callable(Box::new(Native::new("push",push))));
+    env.define("pop",LoxType::Callable(Box::new(Native::new("pop",pop))));
+    env.define("insert",LoxType::Callable(Box::new(Native::new("insert",insert))));
+    env.define("remove",LoxType::Callable(Box::new(Native::new("remove",remove))));
+    env.define("slice",LoxType::Callable(Box::new(Native::new("slice",slice))));
+    env.define("sort",LoxType::Callable(Box::new(Native::new("sort",sort))));
+    env.define("keys",LoxType::Callable(Box::new(Native::new("keys",keys))));
+    env.define("values",LoxType::Callable(Box::new(Native::new("values",values))));
+    env.define("has",LoxType::Callable(Box::new(Native::new("has",has))));
+    env.define("delete",LoxType::Callable(Box::new(Native::new("delete",delete))));
+}
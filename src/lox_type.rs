@@ -1,6 +1,8 @@
-use err::LoxError;
-use super::{Expr,Callable};
+use err::{LoxError,ErrorKind};
+use super::{Expr,Callable,LoxClass,LoxInstance,LoxMap,LoxModule};
 use std::fmt;
+use std::rc::Rc;
+use std::cell::RefCell;
 use std::convert::TryFrom;
 use std::cmp::{Ordering,PartialOrd,PartialEq};
 use std::boxed::Box;
@@ -11,7 +13,54 @@ pub enum LoxType {
     Number(f64),
     Nil,
     Boolean(bool),
-    Callable(Box<Callable>)
+    Callable(Box<Callable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    // Shared, like instances: every copy of a list value sees the same elements
+    List(Rc<RefCell<Vec<LoxType>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Module(Rc<LoxModule>)
+}
+
+thread_local! {
+    // Lists and maps being displayed right now, so one that contains itself prints as [...] or
+    // {...}
+    static DISPLAYING: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+}
+
+impl LoxType {
+    pub fn list(elements: Vec<LoxType>) -> LoxType {
+        LoxType::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn map(map: LoxMap) -> LoxType {
+        LoxType::Map(Rc::new(RefCell::new(map)))
+    }
+
+    // How the value's type is spelled in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            &LoxType::String(_) => "string",
+            &LoxType::Number(_) => "number",
+            &LoxType::Nil => "nil",
+            &LoxType::Boolean(_) => "boolean",
+            &LoxType::Callable(_) => "function",
+            &LoxType::Class(_) => "class",
+            &LoxType::Instance(_) => "instance",
+            &LoxType::List(_) => "list",
+            &LoxType::Map(_) => "map",
+            &LoxType::Module(_) => "module"
+        }
+    }
+
+    // How the value orders against `other` for <, <=, > and >=. Only numbers and strings have
+    // an order, each among its own kind; NaN has none, so every comparison with it is false.
+    pub fn ordering(&self,other: &LoxType) -> Result<Option<Ordering>,LoxError> {
+        match (self,other) {
+            (&LoxType::Number(_),&LoxType::Number(_)) | (&LoxType::String(_),&LoxType::String(_)) => Ok(self.partial_cmp(other)),
+            _ => Err(LoxError::new(format!("Can't compare {} with {}",self.type_name(),other.type_name()),0).of_kind(ErrorKind::Type))
+        }
+    }
 }
 
 impl PartialEq for LoxType {
@@ -21,6 +70,11 @@ impl PartialEq for LoxType {
             (&LoxType::Number(ref s),&LoxType::Number(ref o)) => (s == o),
             (&LoxType::Nil,&LoxType::Nil) => true,
             (&LoxType::Boolean(ref s),&LoxType::Boolean(ref o)) => (s == o),
+            (&LoxType::Class(ref s),&LoxType::Class(ref o)) => Rc::ptr_eq(s,o),
+            (&LoxType::Instance(ref s),&LoxType::Instance(ref o)) => Rc::ptr_eq(s,o),
+            (&LoxType::List(ref s),&LoxType::List(ref o)) => Rc::ptr_eq(s,o),
+            (&LoxType::Map(ref s),&LoxType::Map(ref o)) => Rc::ptr_eq(s,o),
+            (&LoxType::Module(ref s),&LoxType::Module(ref o)) => Rc::ptr_eq(s,o),
             _ => false
         }
     }
@@ -46,18 +100,71 @@ impl fmt::Display for LoxType {
             &LoxType::Number(ref n) => write!(f,"{}",n),
             &LoxType::Boolean(ref b) => write!(f,"{}",b),
             &LoxType::Nil => write!(f,"nil"),
+            &LoxType::Class(ref c) => write!(f,"{}",c.name),
+            &LoxType::Instance(ref i) => write!(f,"{} instance",i.borrow().class.name),
+            &LoxType::List(ref l) => nested(f,l.as_ptr() as usize,"[...]",|f| display_elements(f,&l.borrow())),
+            &LoxType::Map(ref m) => nested(f,m.as_ptr() as usize,"{...}",|f| display_entries(f,&m.borrow())),
+            &LoxType::Module(ref m) => write!(f,"<module {}>",m.name),
             _ => Ok(())
         }
     }
 }
 
+fn nested<F: FnOnce(&mut fmt::Formatter) -> fmt::Result>(f: &mut fmt::Formatter,id: usize,cycle: &str,display: F) -> fmt::Result {
+    if DISPLAYING.with(|d| d.borrow().contains(&id)) {
+        return write!(f,"{}",cycle);
+    }
+
+    DISPLAYING.with(|d| d.borrow_mut().push(id));
+    let res = display(f);
+    DISPLAYING.with(|d| d.borrow_mut().pop());
+    res
+}
+
+// A string as a literal that reads back as the same string. `$` is escaped too, or `${`
+// would start an interpolation.
+pub fn quoted(s: &str) -> String {
+    format!("{:?}",s).replace('$',"\\$")
+}
+
+// Strings inside a collection are quoted, so `[1, "2"]` prints the way it's written
+fn display_element(f: &mut fmt::Formatter,e: &LoxType) -> fmt::Result {
+    match e {
+        &LoxType::String(ref s) => write!(f,"{}",quoted(s)),
+        e => write!(f,"{}",e)
+    }
+}
+
+fn display_elements(f: &mut fmt::Formatter,elements: &Vec<LoxType>) -> fmt::Result {
+    write!(f,"[")?;
+    for (i,e) in elements.iter().enumerate() {
+        if i > 0 {
+            write!(f,", ")?;
+        }
+        display_element(f,e)?;
+    }
+    write!(f,"]")
+}
+
+fn display_entries(f: &mut fmt::Formatter,map: &LoxMap) -> fmt::Result {
+    write!(f,"{{")?;
+    for (i,&(ref k,ref v)) in map.entries().iter().enumerate() {
+        if i > 0 {
+            write!(f,", ")?;
+        }
+        write!(f,"{}: ",k)?;
+        display_element(f,v)?;
+    }
+    write!(f,"}}")
+}
+
 impl TryFrom<LoxType> for f64 {
     type Error = LoxError;
     fn try_from(value: LoxType) -> Result<Self,Self::Error> {
         if let LoxType::Number(n) = value {
             Ok(n)
         } else {
-            Err(LoxError::new("Failed to cast LoxType into f64".to_string(),0))
+            Err(LoxError::new("Failed to cast LoxType into f64".to_string(),0).of_kind(ErrorKind::Type))
         }
     }
 }
@@ -68,7 +175,7 @@ impl TryFrom<LoxType> for String {
         if let LoxType::String(s) = value {
             Ok(s)
         } else {
-            Err(LoxError::new("Failed to cast LoxType into String".to_string(),0))
+            Err(LoxError::new("Failed to cast LoxType into String".to_string(),0).of_kind(ErrorKind::Type))
         }
     }
 }
@@ -79,7 +186,7 @@ impl TryFrom<LoxType> for bool {
         if let LoxType::Boolean(b) = value {
             Ok(b)
         } else {
-            Err(LoxError::new("Failed to cast LoxType into bool".to_string(),0))
+            Err(LoxError::new("Failed to cast LoxType into bool".to_string(),0).of_kind(ErrorKind::Type))
         }
     }
 }
@@ -100,7 +207,7 @@ impl TryFrom<LoxType> for () {
         if let LoxType::Nil = value {
             Ok(())
         } else {
-            Err(LoxError::new("Failed to cast LoxType into ()".to_string(),0))
+            Err(LoxError::new("Failed to cast LoxType into ()".to_string(),0).of_kind(ErrorKind::Type))
         }
     }
 }
@@ -4,14 +4,12 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 
-use lax::{LoxType,Clock};
-use lax::lox_std;
-use lax::scanner::Scanner;
-use lax::parser::Parser;
+use lax::{lox_std,with_script_stack};
 use lax::interpreter::Interpreter;
-use std::io::{self,Read};
-use std::boxed::Box;
+use lax::repl::{self,Repl};
+use std::io::Read;
 use std::fs::File;
+use std::process;
 
 
 use docopt::Docopt;
@@ -20,22 +18,27 @@ const USAGE: &'static str = "
 lax, the simple Rust Lox interpreter
 
 Usage:
-    lax run <file>
-    lax -c <code>
+    lax run [--vm] <file>
+    lax [--vm] -c <code>
     lax (-h | --help)
-    lax
+    lax [--vm]
 
 Options:
     -h --help   Show this screen
     -c  Interpret string passed directly
+    --vm  Compile to bytecode and run it on the virtual machine
 ";
 
+// Where the REPL keeps entries between sessions
+const HISTORY_FILE: &'static str = ".lax_history";
+
 #[derive(Deserialize)]
 struct Args {
     cmd_run: bool,
     arg_file: String,
     arg_code: String,
-    flag_c: bool
+    flag_c: bool,
+    flag_vm: bool
 }
 
 fn main() {
@@ -43,31 +46,30 @@ fn main() {
                             .and_then(|d| d.deserialize())
                             .unwrap_or_else(|e| e.exit());
 
+    // Scripts get a thread with as much stack as the interpreter's limits are sized for
+    if !with_script_stack(|| run(args)) {
+        process::exit(1);
+    }
+}
+
+// Returns whether everything ran without errors, which have all been reported by then
+fn run(args: Args) -> bool {
     let mut interpreter = Interpreter::new();
 
-    interpreter.env.define("clock",LoxType::Callable(Box::new(Clock)));
-    interpreter.env.define("print",LoxType::Callable(Box::new(lox_std::Print)));
-    interpreter.env.define("readline",LoxType::Callable(Box::new(lox_std::Readline)));
+    lox_std::install(&mut interpreter.builtins.borrow_mut());
 
     if args.flag_c {
-        run(args.arg_code,&mut interpreter);
+        repl::run_source(&args.arg_code,"<input>",&mut interpreter,args.flag_vm)
     } else if args.cmd_run {
         let mut buffer = String::new();
-        File::open(args.arg_file).unwrap().read_to_string(&mut buffer).unwrap();
-        run(buffer,&mut interpreter);
-    } else {
-        loop {
-            let mut buffer = String::new();
-            io::stdin().read_line(&mut buffer).unwrap();
-            run(buffer,&mut interpreter);
+        if let Err(e) = File::open(&args.arg_file).and_then(|mut f| f.read_to_string(&mut buffer)) {
+            eprintln!("Can't read {}: {}",args.arg_file,e);
+            return false;
         }
+        interpreter.set_file(&args.arg_file);
+        repl::run_source(&buffer,&args.arg_file,&mut interpreter,args.flag_vm)
+    } else {
+        Repl::new(interpreter,args.flag_vm).history(HISTORY_FILE).run();
+        true
     }
 }
-
-fn run(s: String,i: &mut Interpreter) {
-    let mut scanner = Scanner::new(s);
-    scanner.scan().unwrap();
-    let mut parser = Parser::new(scanner.tokens);
-    let ast = parser.parse().unwrap();
-    i.interpret(&ast).unwrap();
-}
@@ -0,0 +1,69 @@
+use super::{LoxType,LoxError,Environment,Span};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::path::{Path,PathBuf};
+
+// A file loaded by an import. Its globals are what it exports, and it only runs once per
+// interpreter however many times it's imported.
+pub struct LoxModule {
+    // As written in the import that first loaded it
+    pub name: String,
+    pub path: PathBuf,
+    pub globals: Rc<RefCell<Environment>>
+}
+
+impl LoxModule {
+    pub fn new(name: &str,path: PathBuf,globals: Rc<RefCell<Environment>>) -> LoxModule {
+        LoxModule {
+            name: name.to_string(),
+            path: path,
+            globals: globals
+        }
+    }
+
+    // Only the module's own globals, not the natives it can see
+    pub fn get(&self,name: &str,span: Span) -> Result<LoxType,LoxError> {
+        match self.globals.borrow().get_own(name) {
+            Some(v) => Ok(v),
+            None => Err(LoxError::with_span(format!("Module '{}' has no '{}'",self.name,name),span))
+        }
+    }
+
+    pub fn exports(&self) -> Vec<(String,LoxType)> {
+        self.globals.borrow().entries()
+    }
+}
+
+impl fmt::Debug for LoxModule {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"<module {}>",self.name)
+    }
+}
+
+// Finds the file `import "name"` means: next to the importing file (or in the working directory
+// if there isn't one), then in each directory of the search path. ".lox" can be left off.
+// The path comes back canonical so every spelling of it shares one cache entry.
+pub fn resolve_import(name: &str,importer: Option<&Path>,search_path: &[PathBuf]) -> Option<PathBuf> {
+    let requested = Path::new(name);
+    let mut candidates = vec![requested.to_path_buf()];
+    if requested.extension().is_none() {
+        candidates.push(requested.with_extension("lox"));
+    }
+
+    let mut dirs = vec![importer.and_then(|f| f.parent()).map(|d| d.to_path_buf()).unwrap_or_default()];
+    if requested.is_relative() {
+        dirs.extend(search_path.iter().cloned());
+    }
+
+    for dir in dirs {
+        for candidate in &candidates {
+            let path = dir.join(candidate);
+            if path.is_file() {
+                return fs::canonicalize(&path).ok();
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,277 @@
+use super::{Callable,Arity,LoxError,ErrorKind,LoxType,LoxMap};
+use interpreter::Interpreter;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt;
+
+// Rust types a native function can take as a parameter. The conversions are the
+// TryFrom<LoxType> ones; this only adds what type errors should call the expected value.
+pub trait FromLox: Sized {
+    fn from_lox(value: LoxType) -> Result<Self,LoxError>;
+    fn expected() -> String;
+
+    // What to use when the argument is left off entirely; None makes it required
+    fn absent() -> Option<Self> {
+        None
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: LoxType) -> Result<f64,LoxError> {
+        f64::try_from(value)
+    }
+    fn expected() -> String { "number".to_string() }
+}
+
+impl FromLox for String {
+    fn from_lox(value: LoxType) -> Result<String,LoxError> {
+        String::try_from(value)
+    }
+    fn expected() -> String { "string".to_string() }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: LoxType) -> Result<bool,LoxError> {
+        Ok(bool::try_from(value)?)
+    }
+    fn expected() -> String { "boolean".to_string() }
+}
+
+impl FromLox for LoxType {
+    fn from_lox(value: LoxType) -> Result<LoxType,LoxError> {
+        Ok(value)
+    }
+    fn expected() -> String { "value".to_string() }
+}
+
+// The list itself rather than a copy, so natives can change it in place
+impl FromLox for Rc<RefCell<Vec<LoxType>>> {
+    fn from_lox(value: LoxType) -> Result<Rc<RefCell<Vec<LoxType>>>,LoxError> {
+        match value {
+            LoxType::List(l) => Ok(l),
+            _ => Err(LoxError::new("Failed to cast LoxType into list".to_string(),0).of_kind(ErrorKind::Type))
+        }
+    }
+    fn expected() -> String { "list".to_string() }
+}
+
+impl FromLox for Rc<RefCell<LoxMap>> {
+    fn from_lox(value: LoxType) -> Result<Rc<RefCell<LoxMap>>,LoxError> {
+        match value {
+            LoxType::Map(m) => Ok(m),
+            _ => Err(LoxError::new("Failed to cast LoxType into map".to_string(),0).of_kind(ErrorKind::Type))
+        }
+    }
+    fn expected() -> String { "map".to_string() }
+}
+
+// nil comes through as None, and so does an argument left off the end of the call
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: LoxType) -> Result<Option<T>,LoxError> {
+        match value {
+            LoxType::Nil => Ok(None),
+            v => T::from_lox(v).map(Some)
+        }
+    }
+    fn expected() -> String { format!("{} or nil",T::expected()) }
+    fn absent() -> Option<Option<T>> { Some(None) }
+}
+
+// Takes every remaining argument, so it has to be a native's last parameter
+#[derive(Debug,Clone,PartialEq)]
+pub struct Rest<T>(pub Vec<T>);
+
+// How a native's parameter gets its value out of the arguments it was called with
+pub trait NativeParam: Sized {
+    fn take<I: Iterator<Item=(usize,LoxType)>>(function: &str,arguments: &mut I) -> Result<Self,LoxError>;
+
+    // Whether a call can leave it off
+    fn optional() -> bool;
+
+    fn variadic() -> bool {
+        false
+    }
+}
+
+impl<T: FromLox> NativeParam for T {
+    fn take<I: Iterator<Item=(usize,LoxType)>>(function: &str,arguments: &mut I) -> Result<T,LoxError> {
+        match arguments.next() {
+            Some((i,v)) => argument(function,i + 1,v),
+            None => T::absent().ok_or_else(|| LoxError::new("Missing argument".to_string(),0).of_kind(ErrorKind::Arity))
+        }
+    }
+
+    fn optional() -> bool {
+        T::absent().is_some()
+    }
+}
+
+impl<T: FromLox> NativeParam for Rest<T> {
+    fn take<I: Iterator<Item=(usize,LoxType)>>(function: &str,arguments: &mut I) -> Result<Rest<T>,LoxError> {
+        let mut rest = Vec::new();
+        for (i,v) in arguments {
+            rest.push(argument(function,i + 1,v)?);
+        }
+        Ok(Rest(rest))
+    }
+
+    fn optional() -> bool {
+        true
+    }
+
+    fn variadic() -> bool {
+        true
+    }
+}
+
+// Every parameter up to the last required one has to be passed, and a trailing Rest lifts the
+// upper limit. Parameters are given as (optional,variadic).
+fn native_arity(params: &[(bool,bool)]) -> Arity {
+    let min = params.iter().rposition(|&(optional,_)| !optional).map_or(0,|i| i + 1);
+    match params.last() {
+        Some(&(_,true)) => Arity::at_least(min),
+        _ => Arity::range(min,params.len())
+    }
+}
+
+// Rust types a native function can return
+pub trait IntoLox {
+    fn into_lox(self) -> Result<LoxType,LoxError>;
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Result<LoxType,LoxError> { Ok(LoxType::Nil) }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Result<LoxType,LoxError> { Ok(LoxType::Number(self)) }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Result<LoxType,LoxError> { Ok(LoxType::String(self)) }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Result<LoxType,LoxError> { Ok(LoxType::Boolean(self)) }
+}
+
+impl IntoLox for LoxType {
+    fn into_lox(self) -> Result<LoxType,LoxError> { Ok(self) }
+}
+
+impl IntoLox for Vec<LoxType> {
+    fn into_lox(self) -> Result<LoxType,LoxError> { Ok(LoxType::list(self)) }
+}
+
+impl IntoLox for LoxMap {
+    fn into_lox(self) -> Result<LoxType,LoxError> { Ok(LoxType::map(self)) }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Result<LoxType,LoxError> {
+        match self {
+            Some(v) => v.into_lox(),
+            None => Ok(LoxType::Nil)
+        }
+    }
+}
+
+// Natives that can fail hand back the error, and the call site gets filled in for them
+impl<T: IntoLox> IntoLox for Result<T,LoxError> {
+    fn into_lox(self) -> Result<LoxType,LoxError> {
+        self.and_then(IntoLox::into_lox)
+    }
+}
+
+fn argument<T: FromLox>(function: &str,position: usize,value: LoxType) -> Result<T,LoxError> {
+    let found = value.type_name();
+    T::from_lox(value).map_err(|_| {
+        LoxError::new(format!("Argument {} of '{}' must be a {} but got {}",position,function,T::expected(),found),0).of_kind(ErrorKind::Type)
+    })
+}
+
+// Rust closures and functions usable as Lox natives. `Args` is the tuple of parameter types;
+// it only exists to tell the impls for different arities apart. Option parameters at the end
+// can be left off and a Rest parameter at the very end takes any number of arguments.
+pub trait NativeFn<Args>: 'static {
+    fn arity(&self) -> Arity;
+    fn call(&self,name: &str,arguments: Vec<LoxType>) -> Result<LoxType,LoxError>;
+}
+
+macro_rules! native_fn {
+    ($($arg:ident),*) => {
+        impl<F,R,$($arg),*> NativeFn<($($arg,)*)> for F
+            where F: Fn($($arg),*) -> R + 'static,R: IntoLox,$($arg: NativeParam),*
+        {
+            fn arity(&self) -> Arity {
+                let params: &[(bool,bool)] = &[$(($arg::optional(),$arg::variadic())),*];
+                native_arity(params)
+            }
+
+            #[allow(non_snake_case,unused_mut,unused_variables)]
+            fn call(&self,name: &str,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+                let arity = self.arity();
+                if !arity.accepts(arguments.len()) {
+                    return Err(LoxError::new(arity.mismatch(arguments.len()),0).of_kind(ErrorKind::Arity));
+                }
+
+                let mut arguments = arguments.into_iter().enumerate();
+                $(
+                    let $arg = $arg::take(name,&mut arguments)?;
+                )*
+                self($($arg),*).into_lox()
+            }
+        }
+    }
+}
+
+native_fn!();
+native_fn!(A);
+native_fn!(A,B);
+native_fn!(A,B,C);
+native_fn!(A,B,C,D);
+native_fn!(A,B,C,D,E);
+native_fn!(A,B,C,D,E,G);
+
+// A Rust function wrapped up as a Lox value
+#[derive(Clone)]
+pub struct Native {
+    name: String,
+    arity: Arity,
+    fun: Rc<Fn(&str,Vec<LoxType>) -> Result<LoxType,LoxError>>
+}
+
+impl Native {
+    pub fn new<Args,F: NativeFn<Args>>(name: &str,f: F) -> Native {
+        Native {
+            name: name.to_string(),
+            arity: f.arity(),
+            fun: Rc::new(move |name: &str,arguments: Vec<LoxType>| f.call(name,arguments))
+        }
+    }
+}
+
+impl Callable for Native {
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn call(&self,_: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+        (self.fun)(&self.name,arguments)
+    }
+
+    fn box_clone(&self) -> Box<Callable> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"<native fn {}>",self.name)
+    }
+}
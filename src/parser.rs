@@ -1,18 +1,22 @@
 use super::*;
 use std::rc::Rc;
+use std::mem;
+use std::collections::HashSet;
 
 pub enum FunKind {
     Function,
     Method
 }
 
+// Records the error and skips ahead to the next statement instead of bailing out
 macro_rules! try_sync {
     ($self:expr,$e:expr) => {
         match $e {
-            Ok(k) => k,
+            Ok(k) => Some(k),
             Err(e) => {
+                $self.errors.push(e);
                 $self.sync();
-                return Err(e);
+                None
             }
         }
     }
@@ -20,24 +24,40 @@ macro_rules! try_sync {
 
 pub struct Parser {
     current: usize,
-    tokens: Vec<Token>
+    tokens: Vec<Token>,
+    errors: Vec<LoxError>,
+    // How many nested() rules are being parsed
+    depth: usize,
+    // How many loops enclose the statement being parsed, within the current function
+    loops: usize,
+    // Whether the statement is inside a finally block, within the current function
+    finally: bool,
+    // Positions of the '(' tokens that open an arrow function's parameters
+    arrows: HashSet<usize>
 }
 
 type ParseResult = Result<Expr,LoxError>;
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser{
-        Parser { current: 0, tokens: tokens }
+        let arrows = arrows(&tokens);
+        Parser { current: 0, tokens: tokens, errors: Vec::new(), depth: 0, loops: 0, finally: false, arrows: arrows }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Rc<Statement>>,LoxError> {
+    pub fn parse(&mut self) -> Result<Vec<Rc<Statement>>,Vec<LoxError>> {
 //        println!("{:?}",&self.tokens);
         let mut statements: Vec<Rc<Statement>> = Vec::new();
-
         while !self.is_end() {
-            statements.push(Rc::new(self.declaration()?));
+            if let Some(s) = self.declaration() {
+                statements.push(Rc::new(s));
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(self.errors.drain(..).map(|e| e.of_kind(ErrorKind::Syntax)).collect())
         }
-        Ok(statements)
     }
 
     fn sync(&mut self) {
@@ -52,7 +72,10 @@ impl Parser {
                 TokenType::If => return (),
                 TokenType::While => return (),
                 TokenType::Import => return (),
+                TokenType::From => return (),
                 TokenType::Return => return (),
+                TokenType::Throw => return (),
+                TokenType::Try => return (),
                 _ => ()
             };
             self.advance();
@@ -74,7 +97,7 @@ impl Parser {
         if self.check(t) {
             Ok(self.advance())
         } else {
-            Err(LoxError::new(msg,self.peek().line))
+            Err(LoxError::at(msg,&self.peek()))
         }
     }
 
@@ -83,6 +106,10 @@ impl Parser {
         self.peek().token == t
     }
 
+    fn check_next(&self,t: TokenType) -> bool {
+        self.tokens.get(self.current + 1).map_or(false,|next| next.token == t)
+    }
+
     fn advance(&mut self) -> Token {
         if !self.is_end() { self.current += 1; }
         self.previous()
@@ -93,6 +120,28 @@ impl Parser {
         // This probably shouldn't clone, but im tired AND lazy.
     }
 
+    // A '{' starting a statement opens a block, unless there's a ':' after it before anything
+    // that would end a statement or the braces. Blocks never have one at their top level.
+    fn map_ahead(&self) -> bool {
+        match self.tokens.get(self.current) {
+            Some(t) if t.token == TokenType::LeftBrace => (),
+            _ => return false
+        }
+
+        let mut depth = 0;
+        for t in &self.tokens[self.current + 1..] {
+            match t.token {
+                TokenType::LeftBrace | TokenType::LeftParenthesis | TokenType::LeftBracket => depth += 1,
+                TokenType::RightBrace | TokenType::RightParenthesis | TokenType::RightBracket if depth == 0 => return false,
+                TokenType::RightBrace | TokenType::RightParenthesis | TokenType::RightBracket => depth -= 1,
+                TokenType::Colon if depth == 0 => return true,
+                TokenType::Semicolon | TokenType::EOF if depth == 0 => return false,
+                _ => ()
+            }
+        }
+        false
+    }
+
     fn previous(&mut self) -> Token {
         self.tokens[self.current - 1].clone()
     }
@@ -101,13 +150,24 @@ impl Parser {
         self.peek().token == TokenType::EOF
     }
 
+    // Every recursive rule goes through here so absurdly nested source is an error, not a crash
+    fn nested<T,F: FnOnce(&mut Parser) -> Result<T,LoxError>>(&mut self,f: F) -> Result<T,LoxError> {
+        if self.depth >= MAX_NESTING {
+            return Err(LoxError::at("Too much nesting".to_string(),&self.peek()));
+        }
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res
+    }
+
     // Grammar rules
 
-    fn declaration(&mut self) -> Result<Statement,LoxError> {
+    fn declaration(&mut self) -> Option<Statement> {
         if self.match_t(vec![TokenType::Var]) {
-            Ok(try_sync!(self,self.var_statement()))
+            try_sync!(self,self.var_statement())
         } else {
-            Ok(try_sync!(self,self.statement()))
+            try_sync!(self,self.statement())
         }
     }
 
@@ -123,7 +183,13 @@ impl Parser {
     }
 
     fn statement(&mut self) -> Result<Statement,LoxError> {
-        if self.match_t(vec![TokenType::LeftBrace]) {
+        self.nested(|p| p.statement_inner())
+    }
+
+    fn statement_inner(&mut self) -> Result<Statement,LoxError> {
+        if self.map_ahead() || (self.check(TokenType::Fun) && self.check_next(TokenType::LeftParenthesis)) {
+            self.expr_statement()
+        } else if self.match_t(vec![TokenType::LeftBrace]) {
             self.block_statement()
         } else if self.match_t(vec![TokenType::If]) {
             self.if_statement()
@@ -133,41 +199,214 @@ impl Parser {
             self.for_statement()
         } else if self.match_t(vec![TokenType::Fun]) {
             self.function(FunKind::Function)
+        } else if self.match_t(vec![TokenType::Class]) {
+            self.class_declaration()
         } else if self.match_t(vec![TokenType::Return]) {
             self.return_statement()
         } else if self.match_t(vec![TokenType::Import]) {
             self.import_statement()
+        } else if self.match_t(vec![TokenType::From]) {
+            self.from_import()
+        } else if self.match_t(vec![TokenType::Break,TokenType::Continue]) {
+            self.loop_jump()
+        } else if self.match_t(vec![TokenType::Throw]) {
+            self.throw_statement()
+        } else if self.match_t(vec![TokenType::Try]) {
+            self.try_statement()
         } else {
             self.expr_statement()
         }
     }
 
+    fn loop_jump(&mut self) -> Result<Statement,LoxError> {
+        let keyword = self.previous();
+        if self.loops == 0 && self.finally {
+            return Err(LoxError::at(format!("Can't use '{}' to leave a finally block",keyword.lexeme),&keyword));
+        }
+        if self.loops == 0 {
+            return Err(LoxError::at(format!("Can't use '{}' outside of a loop",keyword.lexeme),&keyword));
+        }
+        self.consume(TokenType::Semicolon,format!("Expected ';' after '{}'",keyword.lexeme))?;
+        match keyword.token {
+            TokenType::Break => Ok(Statement::Break(keyword)),
+            _ => Ok(Statement::Continue(keyword))
+        }
+    }
+
+    // Parses a loop body, where break and continue are allowed
+    fn loop_body(&mut self) -> Result<Statement,LoxError> {
+        self.loops += 1;
+        let body = self.statement();
+        self.loops -= 1;
+        body
+    }
+
+    fn throw_statement(&mut self) -> Result<Statement,LoxError> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon,"Expected ';' after thrown value".to_string())?;
+        Ok(Statement::Throw(keyword,value))
+    }
+
+    fn try_statement(&mut self) -> Result<Statement,LoxError> {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftBrace,"Expected '{' after 'try'".to_string())?;
+        let body = Rc::new(self.block_statement()?);
+        let catch = if self.match_t(vec![TokenType::Catch]) {
+            self.consume(TokenType::LeftParenthesis,"Expected '(' after 'catch'".to_string())?;
+            let name = self.consume(TokenType::Identifier,"Expected a name for the caught value".to_string())?;
+            self.consume(TokenType::RightParenthesis,"Expected ')' after the caught name".to_string())?;
+            self.consume(TokenType::LeftBrace,"Expected '{' after 'catch'".to_string())?;
+            Some(Catch { name: name, body: Rc::new(self.block_statement()?) })
+        } else {
+            None
+        };
+        let finally = if self.match_t(vec![TokenType::Finally]) {
+            self.consume(TokenType::LeftBrace,"Expected '{' after 'finally'".to_string())?;
+            // Leaving a finally block early would drop whatever it was finishing, so jumps
+            // out of it aren't allowed; loops inside it still take break and continue.
+            let outer = (mem::replace(&mut self.loops,0),mem::replace(&mut self.finally,true));
+            let block = self.block_statement();
+            self.loops = outer.0;
+            self.finally = outer.1;
+            Some(Rc::new(block?))
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(LoxError::at("Expected 'catch' or 'finally' after try block".to_string(),&keyword));
+        }
+        Ok(Statement::Try(body,catch,finally))
+    }
+
     fn import_statement(&mut self) -> Result<Statement,LoxError> {
         let file = self.consume(TokenType::String,"Expected 'string' after 'import'".to_string())?;
+        let imports = if self.match_t(vec![TokenType::As]) {
+            Imports::Module(self.consume(TokenType::Identifier,"Expected a name after 'as'".to_string())?)
+        } else {
+            Imports::All
+        };
         self.consume(TokenType::Semicolon,"Expected ';' after import statement".to_string())?;
-        Ok(Statement::Import(file))
+        Ok(Statement::Import(file,imports))
+    }
+
+    fn from_import(&mut self) -> Result<Statement,LoxError> {
+        let file = self.consume(TokenType::String,"Expected 'string' after 'from'".to_string())?;
+        self.consume(TokenType::Import,"Expected 'import' after the file name".to_string())?;
+        let mut names = vec![self.consume(TokenType::Identifier,"Expected a name to import".to_string())?];
+        while self.match_t(vec![TokenType::Comma]) {
+            names.push(self.consume(TokenType::Identifier,"Expected a name to import".to_string())?);
+        }
+        self.consume(TokenType::Semicolon,"Expected ';' after import statement".to_string())?;
+        Ok(Statement::Import(file,Imports::Names(names)))
+    }
+
+    fn class_declaration(&mut self) -> Result<Statement,LoxError> {
+        let name = self.consume(TokenType::Identifier,"Expected class name".to_string())?;
+
+        let superclass = if self.match_t(vec![TokenType::Less]) {
+            Some(Expr::Variable(self.consume(TokenType::Identifier,"Expected superclass name".to_string())?,Depth::new(None)))
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace,"Expected '{' before class body".to_string())?;
+        let mut methods: Vec<Rc<Statement>> = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_end() {
+            methods.push(Rc::new(self.function(FunKind::Method)?));
+        }
+
+        self.consume(TokenType::RightBrace,"Expected '}' after class body".to_string())?;
+        Ok(Statement::Class(name,superclass,methods))
     }
 
     fn function(&mut self, kind: FunKind) -> Result<Statement,LoxError> {
-        let name = self.consume(TokenType::Identifier, "Expected function/method name.".to_string())?;
+        let name = match kind {
+            FunKind::Function => self.consume(TokenType::Identifier, "Expected function name.".to_string())?,
+            FunKind::Method => self.consume(TokenType::Identifier, "Expected method name.".to_string())?
+        };
         self.consume(TokenType::LeftParenthesis,"Expected '(' after fun name declaration".to_string())?;
+        let parameters = self.parameters()?;
+        self.consume(TokenType::LeftBrace,"Expected '{' before function/method body".to_string())?;
+        let body = self.function_body()?;
+        Ok(Statement::Function(name,parameters,Rc::new(body)))
+    }
 
-        let mut parameters: Vec<Token> = Vec::new();
+    // The parameter list after its '(', up to and including the ')'
+    fn parameters(&mut self) -> Result<Vec<Parameter>,LoxError> {
+        let mut parameters: Vec<Parameter> = Vec::new();
         if !self.check(TokenType::RightParenthesis) {
-            parameters.push(self.consume(TokenType::Identifier,"Expected parameter name".to_string())?);
+            parameters.push(self.parameter(&parameters)?);
             while self.match_t(vec![TokenType::Comma]) {
-                parameters.push(self.consume(TokenType::Identifier,"Expected parameter name".to_string())?);
+                parameters.push(self.parameter(&parameters)?);
             }
         }
 
         self.consume(TokenType::RightParenthesis,"Expected ')' after parameters.".to_string())?;
-        self.consume(TokenType::LeftBrace,"Expected '{' before function/method body".to_string())?;
-        let body = self.block_statement()?;
-        Ok(Statement::Function(name,parameters,Rc::new(body)))
+        Ok(parameters)
+    }
+
+    // A function's block, after its '{'
+    fn function_body(&mut self) -> Result<Statement,LoxError> {
+        // A loop around the function doesn't make break and continue valid inside it
+        let loops = mem::replace(&mut self.loops,0);
+        let finally = mem::replace(&mut self.finally,false);
+        let body = self.block_statement();
+        self.loops = loops;
+        self.finally = finally;
+        body
+    }
+
+    // `fun (params) { body }` once the 'fun' is consumed
+    fn lambda(&mut self) -> ParseResult {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftParenthesis,"Expected '(' after 'fun'".to_string())?;
+        let parameters = self.parameters()?;
+        self.consume(TokenType::LeftBrace,"Expected '{' before function body".to_string())?;
+        let body = self.function_body()?;
+        Ok(Expr::Lambda(Rc::new(Statement::Function(anonymous(keyword),parameters,Rc::new(body)))))
+    }
+
+    // `(params) => expression` or `(params) => { body }`, once the '(' is consumed
+    fn arrow(&mut self) -> ParseResult {
+        let parameters = self.parameters()?;
+        let arrow = self.consume(TokenType::Arrow,"Expected '=>' after parameters".to_string())?;
+        // A map literal is the body's value, same as at the start of a statement
+        let body = if !self.map_ahead() && self.match_t(vec![TokenType::LeftBrace]) {
+            self.function_body()?
+        } else {
+            let loops = mem::replace(&mut self.loops,0);
+            let e = self.expression();
+            self.loops = loops;
+            Statement::Block(vec![Rc::new(Statement::Return(arrow.clone(),e?))])
+        };
+        Ok(Expr::Lambda(Rc::new(Statement::Function(anonymous(arrow),parameters,Rc::new(body)))))
+    }
+
+    // Whether the '(' coming up starts an arrow function's parameters rather than a grouping
+    fn arrow_ahead(&self) -> bool {
+        self.arrows.contains(&self.current)
+    }
+
+    // `name` or `name = default`
+    fn parameter(&mut self,previous: &Vec<Parameter>) -> Result<Parameter,LoxError> {
+        let name = self.consume(TokenType::Identifier,"Expected parameter name".to_string())?;
+        let default = if self.match_t(vec![TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            if previous.iter().any(|p| p.default.is_some()) {
+                return Err(LoxError::at("Parameters after one with a default need a default too".to_string(),&name));
+            }
+            None
+        };
+        Ok(Parameter { name: name, default: default })
     }
 
     fn return_statement(&mut self) -> Result<Statement,LoxError> {
         let t = self.previous();
+        if self.finally {
+            return Err(LoxError::at("Can't use 'return' to leave a finally block".to_string(),&t));
+        }
         let v = if !self.check(TokenType::Semicolon) { self.expression()? } else { Expr::Literal(LoxType::Nil) };
         self.consume(TokenType::Semicolon,"Expected ';' after return value".to_string())?;
         Ok(Statement::Return(t,v))
@@ -192,13 +431,8 @@ impl Parser {
 
         self.consume(TokenType::RightParenthesis, "Expected ')' after for clauses".to_string())?;
 
-        let mut body = self.statement()?;
-
-        if let Some(i) = increment {
-            body = Statement::Block(vec![Rc::new(body),Rc::new(Statement::Expression(i))]);
-        }
-
-        body = Statement::While(cond,Rc::new(body));
+        let body = self.loop_body()?;
+        let mut body = Statement::While(cond,Rc::new(body),increment);
 
         if let Some(init) = initializer {
             body = Statement::Block(vec![Rc::new(init),Rc::new(body)]);
@@ -211,9 +445,9 @@ impl Parser {
         self.consume(TokenType::LeftParenthesis,"Expected '(' after while".to_string())?;
         let e = self.expression()?;
         self.consume(TokenType::RightParenthesis,"Expected ')' after while".to_string())?;
-        let body = self.statement()?;
+        let body = self.loop_body()?;
 
-        Ok(Statement::While(e,Rc::new(body)))
+        Ok(Statement::While(e,Rc::new(body),None))
     }
 
     fn if_statement(&mut self) -> Result<Statement,LoxError> {
@@ -233,7 +467,9 @@ impl Parser {
     fn block_statement(&mut self) -> Result<Statement,LoxError> {
         let mut statements: Vec<Rc<Statement>> = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.is_end() {
-            statements.push(Rc::new(self.declaration()?));
+            if let Some(s) = self.declaration() {
+                statements.push(Rc::new(s));
+            }
         }
 
         self.consume(TokenType::RightBrace,"Expected '}' after block".to_string())?;
@@ -247,7 +483,7 @@ impl Parser {
     }
 
     fn expression(&mut self) -> ParseResult {
-        self.assignment()
+        self.nested(|p| p.assignment())
     }
 
     fn assignment(&mut self) -> ParseResult {
@@ -255,13 +491,16 @@ impl Parser {
 
         if self.match_t(vec![TokenType::Equal]) {
             let equals = self.previous();
-            let value = self.assignment()?;
+            let value = self.nested(|p| p.assignment())?;
 
-            if let Expr::Variable(name) = e {
-                return Ok(Expr::Assign(name,Rc::new(value)));
+            match e {
+                Expr::Variable(ref name,ref depth) => return Ok(Expr::Assign(name.clone(),Rc::new(value),depth.clone())),
+                Expr::Get(ref object,ref name) => return Ok(Expr::Set(object.clone(),name.clone(),Rc::new(value))),
+                Expr::Index(ref object,ref bracket,ref index) => return Ok(Expr::SetIndex(object.clone(),bracket.clone(),index.clone(),Rc::new(value))),
+                _ => ()
             }
 
-            return Err(LoxError::new("Invalid assignment target".to_string(),equals.line));
+            return Err(LoxError::at("Invalid assignment target".to_string(),&equals));
         }
 
         Ok(e)
@@ -281,7 +520,7 @@ impl Parser {
         let mut e = self.equality()?;
         while self.match_t(vec![TokenType::And]) {
             let op = self.previous();
-            let right = self.and()?;
+            let right = self.equality()?;
             e = Expr::Logical(Rc::new(e),op,Rc::new(right));
         }
         Ok(e)
@@ -337,21 +576,69 @@ impl Parser {
     fn unary(&mut self) -> ParseResult {
         if self.match_t(vec![TokenType::Bang,TokenType::Minus]) {
             let op = self.previous();
-            let right = self.unary()?;
+            let right = self.nested(|p| p.unary())?;
             return Ok(Expr::Unary(op,Rc::new(right)));
         }
 
         self.call()
     }
 
+    // Postfix operators chain in any order and any number, each applying to everything
+    // before it: f(1)(2), xs[0].name, make()[1]()
     fn call(&mut self) -> ParseResult {
         let mut e = self.primary()?;
-        if self.match_t(vec![TokenType::LeftParenthesis]) {
-            e = self.finish_call(e)?;
+        loop {
+            if self.match_t(vec![TokenType::LeftParenthesis]) {
+                e = self.finish_call(e)?;
+            } else if self.match_t(vec![TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier,"Expected property name after '.'".to_string())?;
+                e = Expr::Get(Rc::new(e),name);
+            } else if self.match_t(vec![TokenType::LeftBracket]) {
+                let bracket = self.previous();
+                let index = self.expression()?;
+                self.consume(TokenType::RightBracket,"Expected ']' after index".to_string())?;
+                e = Expr::Index(Rc::new(e),bracket,Rc::new(index));
+            } else {
+                break;
+            }
         }
         Ok(e)
     }
 
+    // "a ${b} c" comes in as Interpolation("a "), the tokens of b, then String(" c"), and turns
+    // into "a " + b + " c". Adding anything to a string stringifies it.
+    fn interpolation(&mut self) -> ParseResult {
+        let mut e = Expr::Literal(self.previous().literal.unwrap_or(LoxType::Nil));
+        loop {
+            let plus = self.plus();
+            let part = self.expression()?;
+            e = Expr::Binary(Rc::new(e),plus,Rc::new(part));
+
+            let plus = self.plus();
+            if !self.match_t(vec![TokenType::Interpolation,TokenType::String]) {
+                return Err(LoxError::at("Expected '}' after interpolated expression".to_string(),&self.peek()));
+            }
+            let rest = self.previous();
+            let last = rest.token == TokenType::String;
+            e = Expr::Binary(Rc::new(e),plus,Rc::new(Expr::Literal(rest.literal.unwrap_or(LoxType::Nil))));
+            if last {
+                return Ok(e);
+            }
+        }
+    }
+
+    // A '+' for desugaring, pointing at the token just consumed
+    fn plus(&mut self) -> Token {
+        let previous = self.previous();
+        Token {
+            token: TokenType::Plus,
+            lexeme: "+".to_string(),
+            line: previous.line,
+            literal: None,
+            span: previous.span
+        }
+    }
+
     fn finish_call(&mut self,expr: Expr) -> ParseResult {
         let mut arguments: Vec<Rc<Expr>> = Vec::new();
         if !self.check(TokenType::RightParenthesis) {
@@ -372,11 +659,35 @@ impl Parser {
         if self.match_t(vec![TokenType::Nil]) { return Ok(Expr::Literal(LoxType::Nil)) }
 
         if self.match_t(vec![TokenType::Number,TokenType::String]) {
-            return Ok(Expr::Literal(self.previous().literal.unwrap()))
+            return Ok(Expr::Literal(self.previous().literal.unwrap_or(LoxType::Nil)))
+        }
+
+        if self.match_t(vec![TokenType::Interpolation]) {
+            return self.interpolation()
+        }
+
+        if self.match_t(vec![TokenType::Fun]) {
+            return self.lambda()
+        }
+
+        if self.check(TokenType::LeftParenthesis) && self.arrow_ahead() {
+            self.advance();
+            return self.arrow()
+        }
+
+        if self.match_t(vec![TokenType::This]) {
+            return Ok(Expr::This(self.previous(),Depth::new(None)))
+        }
+
+        if self.match_t(vec![TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Dot,"Expected '.' after 'super'".to_string())?;
+            let method = self.consume(TokenType::Identifier,"Expected superclass method name".to_string())?;
+            return Ok(Expr::Super(keyword,method,Depth::new(None)))
         }
 
         if self.match_t(vec![TokenType::Identifier]) {
-            return Ok(Expr::Variable(self.previous()))
+            return Ok(Expr::Variable(self.previous(),Depth::new(None)))
         }
 
         if self.match_t(vec![TokenType::LeftParenthesis]) {
@@ -384,6 +695,61 @@ impl Parser {
             self.consume(TokenType::RightParenthesis,"Expected ')' after expression".to_string())?;
             return Ok(Expr::Grouping(Rc::new(e)))
         };
-        Err(LoxError::new("Expected expression".to_string(),self.peek().line))
+
+        if self.match_t(vec![TokenType::LeftBracket]) {
+            let bracket = self.previous();
+            let mut elements: Vec<Rc<Expr>> = Vec::new();
+            while !self.check(TokenType::RightBracket) && !self.is_end() {
+                elements.push(Rc::new(self.expression()?));
+                if !self.match_t(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RightBracket,"Expected ']' after list elements".to_string())?;
+            return Ok(Expr::List(bracket,elements))
+        }
+
+        // Statements starting with '{' were taken as blocks already, so here it's a map
+        if self.match_t(vec![TokenType::LeftBrace]) {
+            let brace = self.previous();
+            let mut entries: Vec<(Rc<Expr>,Rc<Expr>)> = Vec::new();
+            while !self.check(TokenType::RightBrace) && !self.is_end() {
+                let key = self.expression()?;
+                self.consume(TokenType::Colon,"Expected ':' after map key".to_string())?;
+                let value = self.expression()?;
+                entries.push((Rc::new(key),Rc::new(value)));
+                if !self.match_t(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RightBrace,"Expected '}' after map entries".to_string())?;
+            return Ok(Expr::Map(brace,entries))
+        }
+        Err(LoxError::at("Expected expression".to_string(),&self.peek()))
     }
 }
+
+// A '(' opens arrow parameters when its ')' is followed by '=>'. Telling that from a grouping
+// means looking past the whole parenthesis, so every one is matched up front in one pass
+// instead of scanning ahead each time a '(' is parsed.
+fn arrows(tokens: &Vec<Token>) -> HashSet<usize> {
+    let mut arrows = HashSet::new();
+    let mut open = Vec::new();
+    for (i,t) in tokens.iter().enumerate() {
+        match t.token {
+            TokenType::LeftParenthesis => open.push(i),
+            TokenType::RightParenthesis => if let Some(start) = open.pop() {
+                if tokens.get(i + 1).map_or(false,|n| n.token == TokenType::Arrow) {
+                    arrows.insert(start);
+                }
+            },
+            _ => ()
+        }
+    }
+    arrows
+}
+
+// What anonymous functions are called in backtraces. The token keeps the keyword's position.
+fn anonymous(keyword: Token) -> Token {
+    Token { lexeme: "<anonymous>".to_string(), ..keyword }
+}
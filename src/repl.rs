@@ -0,0 +1,292 @@
+use super::*;
+use scanner::Scanner;
+use embed::{parse,evaluate};
+use interpreter::Interpreter;
+use std::fs::{File,OpenOptions};
+use std::io::{Read,Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::mem;
+
+// What errors typed at the prompt say they came from
+const REPL_FILE: &'static str = "<repl>";
+
+const HELP: &'static str = "\
+:env          show everything defined at the top level
+:load <file>  run a file in this session
+:history      show previous entries
+:history <n>  run entry n again
+:help         show this message
+:quit         leave (so does Ctrl-D)
+An entry keeps going while brackets of any kind are open; an empty line sends it as is.
+Expressions entered without a trailing ';' have their value printed.";
+
+// Scans, parses, resolves and runs `source`, reporting every error to stderr instead of
+// stopping at the first one. Returns whether it ran cleanly.
+pub fn run_source(source: &str,file: &str,i: &mut Interpreter,use_vm: bool) -> bool {
+    match parse(source) {
+        Ok(ast) => execute(&ast,source,file,i,use_vm),
+        Err(errors) => {
+            for e in errors {
+                report(e,source,file);
+            }
+            false
+        }
+    }
+}
+
+pub fn report(e: LoxError,source: &str,file: &str) {
+    // Errors raised in an imported module point into its file, which is read again to show them
+    let other = match e.file() {
+        Some(f) if f != file => Some(f.to_string()),
+        _ => None
+    };
+
+    match other {
+        Some(f) => {
+            let mut module = String::new();
+            match File::open(&f).and_then(|mut m| m.read_to_string(&mut module)) {
+                Ok(_) => eprint!("{}",e.render(&module)),
+                Err(_) => eprint!("{}",e)
+            }
+        },
+        None => eprint!("{}",e.in_file(file).render(source))
+    }
+}
+
+// Whether `source` stops partway through a block, a call, a grouping, a list or a string
+pub fn incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source.to_string());
+    let unterminated = match scanner.scan() {
+        Ok(()) => false,
+        Err(errors) => errors.iter().any(|e| e.message() == "Unterminated string")
+    };
+
+    let mut depth = 0;
+    for t in &scanner.tokens {
+        match t.token {
+            TokenType::LeftBrace | TokenType::LeftParenthesis | TokenType::LeftBracket => depth += 1,
+            TokenType::RightBrace | TokenType::RightParenthesis | TokenType::RightBracket => depth -= 1,
+            _ => ()
+        }
+    }
+
+    unterminated || depth > 0
+}
+
+fn execute(ast: &Vec<Rc<Statement>>,source: &str,file: &str,i: &mut Interpreter,use_vm: bool) -> bool {
+    let errors = if use_vm {
+        match vm::Compiler::new().compile(ast).and_then(|script| vm::run(i,script)) {
+            Ok(()) => Vec::new(),
+            Err(e) => vec![e]
+        }
+    } else {
+        match i.interpret(ast) {
+            Ok(()) => Vec::new(),
+            Err(e) => vec![e]
+        }
+    };
+
+    let ok = errors.is_empty();
+    for e in errors {
+        report(e,source,file);
+    }
+    ok
+}
+
+// How the REPL echoes a value back
+fn show(value: &LoxType) -> String {
+    match value {
+        &LoxType::Callable(ref c) => format!("<fn {}>",c.name()),
+        &LoxType::String(ref s) => format!("{:?}",s),
+        v => format!("{}",v)
+    }
+}
+
+// History entries are stored one per line, so newlines inside them are escaped
+fn escape(entry: &str) -> String {
+    entry.replace("\\","\\\\").replace("\n","\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\')
+        }
+    }
+    out
+}
+
+pub struct Repl {
+    interpreter: Interpreter,
+    use_vm: bool,
+    history: Vec<String>,
+    history_file: Option<PathBuf>
+}
+
+impl Repl {
+    pub fn new(interpreter: Interpreter,use_vm: bool) -> Repl {
+        Repl {
+            interpreter: interpreter,
+            use_vm: use_vm,
+            history: Vec::new(),
+            history_file: None
+        }
+    }
+
+    // Loads earlier entries from `path` and appends new ones to it as they're submitted
+    pub fn history<P: Into<PathBuf>>(mut self,path: P) -> Repl {
+        let path = path.into();
+        let mut contents = String::new();
+        if let Ok(mut f) = File::open(&path) {
+            if f.read_to_string(&mut contents).is_ok() {
+                self.history = contents.lines().map(unescape).collect();
+            }
+        }
+        self.history_file = Some(path);
+        self
+    }
+
+    // Entries are read through the interpreter's input, the same reader readline() uses, so a
+    // line after one that calls readline() is what it reads
+    pub fn run(&mut self) {
+        let mut entry = String::new();
+
+        loop {
+            {
+                let output = self.interpreter.output();
+                write!(output,"{}",if entry.is_empty() { "> " } else { "... " }).ok();
+                output.flush().ok();
+            }
+
+            let mut line = String::new();
+            match self.interpreter.read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => ()
+            }
+
+            if entry.is_empty() && line.trim_left().starts_with(':') {
+                // A recalled entry is recorded instead of the :history that ran it
+                if !line.trim().starts_with(":history") {
+                    self.record(line.trim());
+                }
+                if !self.command(line.trim()) {
+                    break;
+                }
+                continue;
+            }
+
+            let blank = line.trim().is_empty();
+            entry.push_str(&line);
+            if !blank && incomplete(&entry) {
+                continue;
+            }
+
+            let source = mem::replace(&mut entry,String::new());
+            if source.trim().is_empty() {
+                continue;
+            }
+            self.record(source.trim());
+            self.eval(&source);
+        }
+        writeln!(self.interpreter.output(),"").ok();
+    }
+
+    // Runs one complete entry. A missing trailing ';' is forgiven, and if the entry is a lone
+    // expression its value gets printed, unless it's nil.
+    pub fn eval(&mut self,source: &str) -> bool {
+        let trimmed = source.trim_right();
+        if !trimmed.ends_with(';') {
+            let patched = format!("{};",trimmed);
+            if let Ok(ast) = parse(&patched) {
+                if ast.len() == 1 {
+                    if let &Statement::Expression(ref e) = ast[0].as_ref() {
+                        return match evaluate(&mut self.interpreter,&Vec::new(),e,self.use_vm) {
+                            Ok(LoxType::Nil) => true,
+                            Ok(value) => {
+                                writeln!(self.interpreter.output(),"{}",show(&value)).ok();
+                                true
+                            },
+                            Err(e) => {
+                                report(e,&patched,REPL_FILE);
+                                false
+                            }
+                        };
+                    }
+                }
+                return execute(&ast,&patched,REPL_FILE,&mut self.interpreter,self.use_vm);
+            }
+        }
+
+        run_source(source,REPL_FILE,&mut self.interpreter,self.use_vm)
+    }
+
+    // Returns false when the session should end
+    fn command(&mut self,line: &str) -> bool {
+        let mut parts = line.splitn(2,char::is_whitespace);
+        let name = parts.next().unwrap_or("");
+        let argument = parts.next().map(|a| a.trim()).unwrap_or("");
+
+        match name {
+            ":quit" | ":q" => return false,
+            ":help" => { writeln!(self.interpreter.output(),"{}",HELP).ok(); },
+            ":env" => {
+                let entries = self.interpreter.env.borrow().entries();
+                for (name,value) in entries {
+                    writeln!(self.interpreter.output(),"{} = {}",name,show(&value)).ok();
+                }
+            },
+            ":history" if argument.is_empty() => {
+                for (n,entry) in self.history.iter().enumerate() {
+                    writeln!(self.interpreter.output(),"{:4}  {}",n + 1,entry).ok();
+                }
+            },
+            ":history" => {
+                let entry = argument.parse::<usize>().ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|n| self.history.get(n).cloned());
+                match entry {
+                    Some(ref entry) if entry.starts_with(":history") => eprintln!("error: entry {} is a :history itself",argument),
+                    Some(entry) => {
+                        writeln!(self.interpreter.output(),"{}",entry).ok();
+                        self.record(&entry);
+                        if entry.starts_with(':') {
+                            return self.command(&entry);
+                        }
+                        self.eval(&entry);
+                    },
+                    None => eprintln!("error: there's no entry {} in the history",argument)
+                }
+            },
+            ":load" if argument.is_empty() => eprintln!("error: :load needs a file name"),
+            ":load" => {
+                let mut source = String::new();
+                match File::open(argument).and_then(|mut f| f.read_to_string(&mut source)) {
+                    Ok(_) => { run_source(&source,argument,&mut self.interpreter,self.use_vm); },
+                    Err(e) => eprintln!("error: couldn't read {}: {}",argument,e)
+                }
+            },
+            _ => eprintln!("error: unknown command {}, try :help",name)
+        }
+        true
+    }
+
+    fn record(&mut self,entry: &str) {
+        self.history.push(entry.to_string());
+
+        // Losing history isn't worth interrupting the session over
+        if let Some(ref path) = self.history_file {
+            if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(path) {
+                writeln!(f,"{}",escape(entry)).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use super::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Clone,Copy,PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer
+}
+
+#[derive(Clone,Copy,PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass
+}
+
+// Walks the AST once before it runs, binding every local to the scope it lives in.
+// The scopes mirror the environments the interpreter creates: one per block, one
+// per call (parameters and body share it), one for 'this' and one for 'super'.
+pub struct Resolver {
+    scopes: Vec<HashMap<String,bool>>,
+    function: FunctionType,
+    class: ClassType,
+    errors: Vec<LoxError>,
+    // How deep statements and expressions are nested where resolution has got to
+    depth: usize
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            scopes: Vec::new(),
+            function: FunctionType::None,
+            class: ClassType::None,
+            errors: Vec::new(),
+            depth: 0
+        }
+    }
+
+    pub fn resolve(&mut self,statements: &Vec<Rc<Statement>>) -> Result<(),Vec<LoxError>> {
+        self.resolve_statements(statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.drain(..).collect())
+        }
+    }
+
+    fn error(&mut self,msg: &str,t: &Token) {
+        self.errors.push(LoxError::at(msg.to_string(),t).of_kind(ErrorKind::Resolution));
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self,name: &Token) {
+        let duplicate = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.lexeme.clone(),false).is_some(),
+            None => false
+        };
+
+        if duplicate {
+            self.error("Already a variable with this name in this scope",name);
+        }
+    }
+
+    fn define(&mut self,name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(),true);
+        }
+    }
+
+    fn resolve_local(&mut self,name: &str,depth: &Depth) {
+        for (i,scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(name) {
+                depth.set(Some(i));
+                return;
+            }
+        }
+        // Not found: left as a global
+    }
+
+    fn resolve_statements(&mut self,statements: &Vec<Rc<Statement>>) {
+        for s in statements {
+            self.resolve_statement(s);
+        }
+    }
+
+    fn resolve_function(&mut self,params: &Vec<Parameter>,body: &Statement,kind: FunctionType) {
+        let enclosing = self.function;
+        self.function = kind;
+
+        self.begin_scope();
+        // A default sees the parameters before it, same as LoxFun::call binds them
+        for p in params {
+            if let Some(ref default) = p.default {
+                self.resolve_expr(default);
+            }
+            self.declare(&p.name);
+            self.define(&p.name.lexeme);
+        }
+        // The body shares the parameters' scope, same as LoxFun::call
+        if let &Statement::Block(ref statements) = body {
+            self.resolve_statements(statements);
+        }
+        self.end_scope();
+
+        self.function = enclosing;
+    }
+
+    // Statements count toward the depth resolve_expr() checks, so together they can't nest
+    // any deeper than MAX_NESTING. That bounds the stack a call takes on the tree-walker.
+    fn resolve_statement(&mut self,s: &Statement) {
+        self.depth += 1;
+        self.resolve_statement_inner(s);
+        self.depth -= 1;
+    }
+
+    fn resolve_statement_inner(&mut self,s: &Statement) {
+        match s {
+            &Statement::Expression(ref e) => self.resolve_expr(e),
+            &Statement::Variable(ref name,ref init) => {
+                self.declare(name);
+                if let &Some(ref i) = init {
+                    self.resolve_expr(i);
+                }
+                self.define(&name.lexeme);
+            },
+            &Statement::Block(ref statements) => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            },
+            &Statement::If(ref cond,ref then,ref or) => {
+                self.resolve_expr(cond);
+                self.resolve_statement(then);
+                if let &Some(ref or_branch) = or {
+                    self.resolve_statement(or_branch);
+                }
+            },
+            &Statement::While(ref cond,ref body,ref increment) => {
+                self.resolve_expr(cond);
+                self.resolve_statement(body);
+                if let &Some(ref i) = increment {
+                    self.resolve_expr(i);
+                }
+            },
+            &Statement::Function(ref name,ref params,ref body) => {
+                self.declare(name);
+                self.define(&name.lexeme);
+                self.resolve_function(params,body,FunctionType::Function);
+            },
+            &Statement::Return(ref t,ref e) => {
+                if self.function == FunctionType::None {
+                    self.error("Can't return from top-level code",t);
+                }
+
+                if self.function == FunctionType::Initializer {
+                    match e {
+                        &Expr::Literal(LoxType::Nil) => (),
+                        _ => self.error("Can't return a value from an initializer",t)
+                    }
+                }
+
+                self.resolve_expr(e);
+            },
+            &Statement::Throw(_,ref e) => self.resolve_expr(e),
+            &Statement::Try(ref body,ref catch,ref finally) => {
+                self.resolve_statement(body);
+                // The caught value gets a scope of its own around the catch block
+                if let &Some(ref c) = catch {
+                    self.begin_scope();
+                    self.declare(&c.name);
+                    self.define(&c.name.lexeme);
+                    self.resolve_statement(&c.body);
+                    self.end_scope();
+                }
+                if let &Some(ref f) = finally {
+                    self.resolve_statement(f);
+                }
+            },
+            &Statement::Import(_,Imports::Module(ref name)) => {
+                self.declare(name);
+                self.define(&name.lexeme);
+            },
+            &Statement::Import(_,Imports::Names(ref names)) => {
+                for name in names {
+                    self.declare(name);
+                    self.define(&name.lexeme);
+                }
+            },
+            &Statement::Import(_,Imports::All) | &Statement::Break(_) | &Statement::Continue(_) => (),
+            &Statement::Class(ref name,ref superclass,ref methods) => {
+                let enclosing = self.class;
+                self.class = ClassType::Class;
+
+                self.declare(name);
+                self.define(&name.lexeme);
+
+                if let &Some(ref e) = superclass {
+                    if let &Expr::Variable(ref t,_) = e {
+                        if t.lexeme == name.lexeme {
+                            self.error("A class can't inherit from itself",t);
+                        }
+                    }
+                    self.class = ClassType::Subclass;
+                    self.resolve_expr(e);
+
+                    self.begin_scope();
+                    self.define("super");
+                }
+
+                self.begin_scope();
+                self.define("this");
+
+                for m in methods {
+                    if let &Statement::Function(ref t,ref params,ref body) = m.as_ref() {
+                        let kind = if t.lexeme == "init" { FunctionType::Initializer } else { FunctionType::Method };
+                        self.resolve_function(params,body,kind);
+                    }
+                }
+
+                self.end_scope();
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.class = enclosing;
+            }
+        }
+    }
+
+    // Postfix chains like a.b.c() build trees as deep as they are long, which the parser's
+    // nesting limit doesn't cover
+    fn resolve_expr(&mut self,e: &Expr) {
+        if self.depth >= MAX_NESTING {
+            let msg = "Too much nesting".to_string();
+            let e = match e.token() {
+                Some(t) => LoxError::at(msg,t),
+                None => LoxError::new(msg,0)
+            };
+            self.errors.push(e.of_kind(ErrorKind::Resolution));
+            return;
+        }
+        self.depth += 1;
+        self.resolve_expr_inner(e);
+        self.depth -= 1;
+    }
+
+    fn resolve_expr_inner(&mut self,e: &Expr) {
+        match e {
+            &Expr::Variable(ref name,ref depth) => {
+                let uninitialized = match self.scopes.last() {
+                    Some(scope) => scope.get(&name.lexeme) == Some(&false),
+                    None => false
+                };
+
+                if uninitialized {
+                    self.error("Can't read local variable in its own initializer",name);
+                }
+
+                self.resolve_local(&name.lexeme,depth);
+            },
+            &Expr::Assign(ref name,ref value,ref depth) => {
+                self.resolve_expr(value);
+                self.resolve_local(&name.lexeme,depth);
+            },
+            &Expr::Binary(..) => {
+                let (first,links) = e.chain();
+                self.resolve_expr(first);
+                for (_,right) in links {
+                    self.resolve_expr(right);
+                }
+            },
+            &Expr::Logical(..) => {
+                let (first,links) = e.chain();
+                self.resolve_expr(first);
+                for (_,right) in links {
+                    self.resolve_expr(right);
+                }
+            },
+            &Expr::Grouping(ref e) => self.resolve_expr(e),
+            &Expr::Literal(_) => (),
+            &Expr::Unary(_,ref e) => self.resolve_expr(e),
+            &Expr::Call(ref callee,_,ref args) => {
+                self.resolve_expr(callee);
+                for a in args {
+                    self.resolve_expr(a);
+                }
+            },
+            &Expr::Get(ref object,_) => self.resolve_expr(object),
+            &Expr::Set(ref object,_,ref value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            },
+            &Expr::This(ref keyword,ref depth) => {
+                if self.class == ClassType::None {
+                    self.error("Can't use 'this' outside of a class",keyword);
+                    return;
+                }
+                self.resolve_local("this",depth);
+            },
+            &Expr::Super(ref keyword,_,ref depth) => {
+                match self.class {
+                    ClassType::None => self.error("Can't use 'super' outside of a class",keyword),
+                    ClassType::Class => self.error("Can't use 'super' in a class with no superclass",keyword),
+                    ClassType::Subclass => self.resolve_local("super",depth)
+                }
+            },
+            &Expr::List(_,ref elements) => {
+                for e in elements {
+                    self.resolve_expr(e);
+                }
+            },
+            &Expr::Map(_,ref entries) => {
+                for &(ref k,ref v) in entries {
+                    self.resolve_expr(k);
+                    self.resolve_expr(v);
+                }
+            },
+            &Expr::Index(ref object,_,ref index) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            },
+            &Expr::SetIndex(ref object,_,ref index,ref value) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(value);
+            },
+            &Expr::Lambda(ref declaration) => {
+                if let &Statement::Function(_,ref params,ref body) = declaration.as_ref() {
+                    self.resolve_function(params,body,FunctionType::Function);
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::{TokenType,LoxError,Token,LoxType};
+use super::{TokenType,LoxError,ErrorKind,Token,LoxType,Span};
 use std::ascii::AsciiExt;
 use std::collections::HashMap;
 use std::io;
@@ -22,16 +22,31 @@ lazy_static! {
                 m.insert("var",TokenType::Var);
                 m.insert("while",TokenType::While);
                 m.insert("import",TokenType::Import);
+                m.insert("from",TokenType::From);
+                m.insert("as",TokenType::As);
+                m.insert("break",TokenType::Break);
+                m.insert("continue",TokenType::Continue);
+                m.insert("throw",TokenType::Throw);
+                m.insert("try",TokenType::Try);
+                m.insert("catch",TokenType::Catch);
+                m.insert("finally",TokenType::Finally);
                 m
             };
 }
 
 pub struct Scanner {
     source: Vec<char>,
+    // Byte offset of every char, plus one past the end
+    offsets: Vec<usize>,
     pub tokens: Vec<Token>,
     current: i32,
     line: i32,
-    start: i32
+    line_start: i32,
+    start: i32,
+    start_line: i32,
+    start_column: usize,
+    // One entry per `${` we're inside of, counting the braces opened since
+    interpolations: Vec<usize>
 }
 
 macro_rules! add_match {
@@ -46,52 +61,94 @@ macro_rules! add_match {
 
 impl Scanner {
     pub fn new(s: String) -> Scanner {
+        let mut offsets = s.char_indices().map(|(i,_)| i).collect::<Vec<usize>>();
+        offsets.push(s.len());
         Scanner {
             source: s.chars().collect::<Vec<char>>(),
+            offsets: offsets,
             tokens: Vec::new(),
             current: 0,
-            line: 0,
-            start: 0
+            line: 1,
+            line_start: 0,
+            start: 0,
+            start_line: 1,
+            start_column: 1,
+            interpolations: Vec::new()
         }
     }
 
+    // Keeps going past bad tokens so every lexical error in the source is reported at once.
     pub fn scan(&mut self) -> Result<(),Vec<LoxError>> {
         let mut errors: Vec<LoxError> = Vec::new();
-        let mut failed = false;
         while !(self.is_end()) {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = (self.current - self.line_start) as usize + 1;
             if let Err(errs)  = self.scan_token() {
                 errors.extend(errs);
-                failed = true;
             }
         }
-        self.tokens.push(Token { token: TokenType::EOF, lexeme:"".to_string(), line:self.line, literal: None });
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = (self.current - self.line_start) as usize + 1;
+        let span = self.span();
+        if !self.interpolations.is_empty() {
+            errors.push(LoxError::with_span("Unterminated string".to_string(),span));
+        }
+        self.tokens.push(Token { token: TokenType::EOF, lexeme:"".to_string(), line:self.start_line, literal: None, span: span });
 
-        if failed {
-            Err(errors)
-        } else {
+        if errors.is_empty() {
             Ok(())
+        } else {
+            Err(errors.into_iter().map(|e| e.of_kind(ErrorKind::Lexical)).collect())
         }
     }
 
     fn scan_token(&mut self) -> Result<(),Vec<LoxError>> {
         let mut errs: Vec<LoxError> = Vec::new();
-        let mut failed = false;
         let c = self.advance();
 //        println!("{}",&c);
         match c {
             '(' => { self.add(TokenType::LeftParenthesis); },
             ')' => { self.add(TokenType::RightParenthesis); },
-            '{' => { self.add(TokenType::LeftBrace); },
-            '}' => { self.add(TokenType::RightBrace); },
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add(TokenType::LeftBrace);
+            },
+            '}' => {
+                // The brace closing a `${` picks the string back up
+                match self.interpolations.pop() {
+                    Some(0) => {
+                        if let Err(e) = self.string() {
+                            errs.push(e);
+                        }
+                    },
+                    Some(depth) => {
+                        self.interpolations.push(depth - 1);
+                        self.add(TokenType::RightBrace);
+                    },
+                    None => self.add(TokenType::RightBrace)
+                }
+            },
+            '[' => { self.add(TokenType::LeftBracket); },
+            ']' => { self.add(TokenType::RightBracket); },
             ',' => { self.add(TokenType::Comma); },
+            ':' => { self.add(TokenType::Colon); },
             '.' => { self.add(TokenType::Dot); },
             '-' => { self.add(TokenType::Minus); },
             '+' => { self.add(TokenType::Plus); },
             ';' => { self.add(TokenType::Semicolon); },
             '*' => { self.add(TokenType::Star); },
             '!' => { add_match!(self,'=',TokenType::BangEqual,TokenType::Bang); },
-            '=' => { add_match!(self,'=',TokenType::EqualEqual,TokenType::Equal); },
+            '=' => {
+                if self.match_c('>') {
+                    self.add(TokenType::Arrow);
+                } else {
+                    add_match!(self,'=',TokenType::EqualEqual,TokenType::Equal);
+                }
+            },
             '<' => { add_match!(self,'=',TokenType::LessEqual,TokenType::Less); },
             '>' => { add_match!(self,'=',TokenType::GreaterEqual,TokenType::Greater); },
             '/' => {
@@ -108,7 +165,7 @@ impl Scanner {
                     errs.push(e);
                 }
             },
-            '\n' => { self.line += 1; },
+            '\n' => (),
             ' ' => (),
             '\r' => (),
             '\t' => (),
@@ -116,37 +173,54 @@ impl Scanner {
                 if c.is_ascii_digit() {
                     if let Err(e) = self.number() {
                         errs.push(e);
-                        failed = true;
                     };
                 } else if c.is_alphabetic() || c == '_' || c == '-' {
                     self.identifier();
                 } else {
-                    errs.push(LoxError::new("Unexpected character".to_string(),self.line));
+                    errs.push(LoxError::with_span("Unexpected character".to_string(),self.span()));
                 }
             }
         };
 
-        if failed {
-            Err(errs)
-        } else {
+        if errs.is_empty() {
             Ok(())
+        } else {
+            Err(errs)
         }
     }
 
     fn advance(&mut self) -> char {
         self.current += 1;
-        self.source[(self.current - 1) as usize]
+        let c = self.source[(self.current - 1) as usize];
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.current;
+        }
+        c
+    }
+
+    // Span of the token currently being scanned
+    fn span(&self) -> Span {
+        let offset = self.offsets[self.start as usize];
+        Span {
+            offset: offset,
+            line: self.start_line,
+            column: self.start_column,
+            length: self.offsets[self.current as usize] - offset
+        }
     }
 
     fn add(&mut self,t: TokenType) {
   //      println!("{:?}",t);
         let lexeme = (&self.source[self.start as usize..self.current as usize]).iter().collect::<String>();
-        self.tokens.push(Token { token: t, lexeme: lexeme, line: self.line, literal: None });
+        let span = self.span();
+        self.tokens.push(Token { token: t, lexeme: lexeme, line: self.start_line, literal: None, span: span });
     }
 
     fn add_token(&mut self,t: TokenType,l: LoxType) {
         let lexeme = (&self.source[self.start as usize..self.current as usize]).iter().collect::<String>();
-        self.tokens.push(Token { token: t, lexeme: lexeme, line: self.line, literal: Some(l) });
+        let span = self.span();
+        self.tokens.push(Token { token: t, lexeme: lexeme, line: self.start_line, literal: Some(l), span: span });
     }
 
     fn match_c(&mut self, expected: char) -> bool {
@@ -177,22 +251,87 @@ impl Scanner {
         }
     }
 
+    // Scans up to the closing quote, or up to a `${`. The part before a `${` becomes an
+    // Interpolation token, and once the expression's '}' is reached the rest gets scanned the
+    // same way.
     fn string(&mut self) -> Result<(),LoxError> {
-        while self.peek() != '"' && !(self.is_end()) {
-            if self.peek() == '\n' {
-                self.line += 1;
+        let mut value = String::new();
+        let mut error = None;
+        let token = loop {
+            if self.is_end() {
+                return Err(LoxError::with_span("Unterminated string".to_string(),self.span()));
+            }
+
+            match self.advance() {
+                '"' => break TokenType::String,
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.interpolations.push(0);
+                    break TokenType::Interpolation;
+                },
+                '\\' => match self.escape() {
+                    Ok(c) => value.push(c),
+                    Err(e) => {
+                        error = error.or(Some(e));
+                    }
+                },
+                c => value.push(c)
+            }
+        };
+
+        match error {
+            Some(e) => Err(e),
+            None => {
+                self.add_token(token,LoxType::String(value));
+                Ok(())
             }
-            self.advance();
         }
+    }
+
+    // The character a backslash escape stands for; the backslash is already consumed
+    fn escape(&mut self) -> Result<char,LoxError> {
+        let start = self.current - 1;
+        let span = |s: &Scanner| {
+            let offset = s.offsets[start as usize];
+            Span {
+                offset: offset,
+                line: s.line,
+                column: (start - s.line_start) as usize + 1,
+                length: s.offsets[s.current as usize] - offset
+            }
+        };
 
         if self.is_end() {
-            return Err(LoxError::new("Unterminated string".to_string(),self.line));
+            return Err(LoxError::with_span("Unterminated string".to_string(),self.span()));
         }
 
-        self.advance();
-        let s = (&self.source[(self.start + 1) as usize..(self.current - 1) as usize]).iter().collect::<String>();
-        self.add_token(TokenType::String,LoxType::String(s));
-        Ok(())
+        let c = match self.advance() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' => '"',
+            '\\' => '\\',
+            '$' => '$',
+            'u' => {
+                // \u{...} with one to six hex digits
+                let mut digits = String::new();
+                if self.match_c('{') {
+                    while self.peek().is_ascii_hexdigit() && digits.len() < 6 {
+                        digits.push(self.advance());
+                    }
+                }
+                if digits.is_empty() || !self.match_c('}') {
+                    return Err(LoxError::with_span("Expected \\u{...} with 1 to 6 hex digits".to_string(),span(self)));
+                }
+                match u32::from_str_radix(&digits,16).ok().and_then(::std::char::from_u32) {
+                    Some(c) => c,
+                    None => return Err(LoxError::with_span(format!("\\u{{{}}} isn't a valid character",digits),span(self)))
+                }
+            },
+            c => return Err(LoxError::with_span(format!("Unknown escape sequence '\\{}'",c),span(self)))
+        };
+        Ok(c)
     }
 
     fn number(&mut self) -> Result<(),LoxError> {
@@ -209,7 +348,7 @@ impl Scanner {
         let s = &self.source[self.start as usize..self.current as usize].iter().collect::<String>();
         match s.parse::<f64>() {
             Ok(n) => self.add_token(TokenType::Number,LoxType::Number(n)),
-            Err(e) => return Err(LoxError::with_lower("Invalid number".to_string(),self.line,io::Error::new(io::ErrorKind::Other,e)))
+            Err(e) => return Err(LoxError::with_lower("Invalid number".to_string(),self.start_line,io::Error::new(io::ErrorKind::Other,e)).located(self.span()))
         };
         Ok(())
     }
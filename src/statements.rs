@@ -2,16 +2,49 @@ use super::{Token,Expr};
 use std::rc::Rc;
 use std::fmt;
 
+// A parameter of a function declaration. Ones with a default can be left off the end of a
+// call; the default is evaluated each time, after the parameters before it are bound.
+#[derive(Debug,Clone)]
+pub struct Parameter {
+    pub name: Token,
+    pub default: Option<Expr>
+}
+
+// What an import binds in the importing scope
+#[derive(Debug,Clone)]
+pub enum Imports {
+    // import "x"; copies every global of the module
+    All,
+    // import "x" as m; binds the module itself, its globals are read as m.name
+    Module(Token),
+    // from "x" import a, b;
+    Names(Vec<Token>)
+}
+
+// The `catch (name) { body }` of a try statement
+#[derive(Debug,Clone)]
+pub struct Catch {
+    pub name: Token,
+    pub body: Rc<Statement>
+}
+
 #[derive(Debug,Clone)]
 pub enum Statement {
     Expression(Expr),
     Variable(Token,Option<Expr>),
     Block(Vec<Rc<Statement>>),
     If(Expr,Rc<Statement>,Option<Rc<Statement>>),
-    While(Expr,Rc<Statement>),
-    Function(Token,Vec<Token>,Rc<Statement>),
+    // The last part is a for loop's increment, which `continue` doesn't skip
+    While(Expr,Rc<Statement>,Option<Expr>),
+    Function(Token,Vec<Parameter>,Rc<Statement>),
     Return(Token,Expr),
-    Import(Token)
+    Import(Token,Imports),
+    Break(Token),
+    Continue(Token),
+    Throw(Token,Expr),
+    // A try block needs a catch, a finally or both
+    Try(Rc<Statement>,Option<Catch>,Option<Rc<Statement>>),
+    Class(Token,Option<Expr>,Vec<Rc<Statement>>)
 }
 
 impl fmt::Display for Statement {
@@ -35,13 +68,39 @@ impl fmt::Display for Statement {
                 }
                 Ok(())
             },
-            &Statement::While(ref c,ref b) => {
+            &Statement::While(ref c,ref b,ref i) => {
                 writeln!(f,"while {}",c)?;
-                writeln!(f,"{}",b)
+                writeln!(f,"{}",b)?;
+                if let &Some(ref increment) = i {
+                    writeln!(f,"then {}",increment)?;
+                }
+                Ok(())
             },
             &Statement::Function(ref t,_,_) => writeln!(f,"<fn {}>",t.lexeme),
             &Statement::Return(_,ref e) => writeln!(f,"return {}",e),
-            &Statement::Import(ref t) => writeln!(f,"import {}",t.lexeme)
+            &Statement::Import(ref t,Imports::All) => writeln!(f,"import {}",t.lexeme),
+            &Statement::Import(ref t,Imports::Module(ref name)) => writeln!(f,"import {} as {}",t.lexeme,name.lexeme),
+            &Statement::Import(ref t,Imports::Names(ref names)) => {
+                let names = names.iter().map(|n| n.lexeme.as_str()).collect::<Vec<&str>>();
+                writeln!(f,"from {} import {}",t.lexeme,names.join(", "))
+            },
+            &Statement::Break(_) => writeln!(f,"break;"),
+            &Statement::Continue(_) => writeln!(f,"continue;"),
+            &Statement::Throw(_,ref e) => writeln!(f,"throw {};",e),
+            &Statement::Try(ref b,ref c,ref fin) => {
+                writeln!(f,"try")?;
+                writeln!(f,"{}",b)?;
+                if let &Some(ref c) = c {
+                    writeln!(f,"catch ({})",c.name.lexeme)?;
+                    writeln!(f,"{}",c.body)?;
+                }
+                if let &Some(ref fin) = fin {
+                    writeln!(f,"finally")?;
+                    writeln!(f,"{}",fin)?;
+                }
+                Ok(())
+            },
+            &Statement::Class(ref t,_,_) => writeln!(f,"<class {}>",t.lexeme)
         }
     }
 }
@@ -1,11 +1,20 @@
 use std::fmt;
 use super::{LoxType,TokenType};
 
+// Where a token sits in its source file
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct Span {
+    pub offset: usize, // in bytes from the start of the source
+    pub line: i32,
+    pub column: usize, // in characters, starting at 1
+    pub length: usize  // in bytes
+}
+
 #[derive(Debug,Clone)]
 pub struct Token {
     pub token: TokenType,
     pub lexeme: String,
     pub line: i32,
-    pub literal: Option<LoxType>
+    pub literal: Option<LoxType>,
+    pub span: Span
 }
-
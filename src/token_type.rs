@@ -4,7 +4,10 @@ pub enum TokenType {
     RightParenthesis,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
@@ -15,12 +18,15 @@ pub enum TokenType {
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
     Identifier,
     String,
+    // The part of a string literal before a `${`
+    Interpolation,
     Number,
     And,
     Class,
@@ -39,5 +45,13 @@ pub enum TokenType {
     Var,
     While,
     Import,
+    From,
+    As,
+    Break,
+    Continue,
+    Throw,
+    Try,
+    Catch,
+    Finally,
     EOF
 }
@@ -0,0 +1,236 @@
+use super::super::{LoxType,Span};
+use std::rc::Rc;
+use std::fmt;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum OpCode {
+    Constant,     // u16 constant
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,     // u8 depth, u8 slot
+    SetLocal,     // u8 depth, u8 slot
+    DefineLocal,  // u8 slot
+    GetGlobal,    // u16 name
+    SetGlobal,    // u16 name
+    DefineGlobal, // u16 name
+    GetProperty,  // u16 name
+    SetProperty,  // u16 name
+    GetSuper,     // u16 name
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Jump,         // u16 offset
+    JumpIfFalse,  // u16 offset
+    JumpIfPassed, // u8 parameter, u16 offset
+    Loop,         // u16 offset
+    Call,         // u8 argument count
+    Closure,      // u16 function
+    Return,
+    PushScope,    // u8 slots
+    PopScope,
+    Inherit,
+    Class,        // u16 name, u8 methods, u8 has superclass
+    Import,       // u16 path
+    ImportAll,
+    List,         // u16 elements
+    Map,          // u16 entries
+    GetIndex,
+    SetIndex,
+    PushCatch,    // u16 offset
+    PushFinally,  // u16 offset
+    PopHandler,
+    Throw,
+    Rethrow
+}
+
+const OPCODES: [OpCode; 48] = [
+    OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False, OpCode::Pop,
+    OpCode::GetLocal, OpCode::SetLocal, OpCode::DefineLocal,
+    OpCode::GetGlobal, OpCode::SetGlobal, OpCode::DefineGlobal,
+    OpCode::GetProperty, OpCode::SetProperty, OpCode::GetSuper,
+    OpCode::Equal, OpCode::NotEqual, OpCode::Greater, OpCode::GreaterEqual, OpCode::Less, OpCode::LessEqual,
+    OpCode::Add, OpCode::Subtract, OpCode::Multiply, OpCode::Divide, OpCode::Not, OpCode::Negate,
+    OpCode::Jump, OpCode::JumpIfFalse, OpCode::JumpIfPassed, OpCode::Loop,
+    OpCode::Call, OpCode::Closure, OpCode::Return,
+    OpCode::PushScope, OpCode::PopScope,
+    OpCode::Inherit, OpCode::Class, OpCode::Import, OpCode::ImportAll,
+    OpCode::List, OpCode::Map, OpCode::GetIndex, OpCode::SetIndex,
+    OpCode::PushCatch, OpCode::PushFinally, OpCode::PopHandler, OpCode::Throw, OpCode::Rethrow
+];
+
+impl OpCode {
+    pub fn from_byte(b: u8) -> Option<OpCode> {
+        OPCODES.get(b as usize).cloned()
+    }
+
+    // Bytes of operands following the opcode itself
+    pub fn operands(&self) -> usize {
+        match *self {
+            OpCode::Constant | OpCode::GetGlobal | OpCode::SetGlobal | OpCode::DefineGlobal |
+            OpCode::GetProperty | OpCode::SetProperty | OpCode::GetSuper |
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop | OpCode::Closure | OpCode::Import |
+            OpCode::List | OpCode::Map | OpCode::PushCatch | OpCode::PushFinally => 2,
+            OpCode::GetLocal | OpCode::SetLocal => 2,
+            OpCode::JumpIfPassed => 3,
+            OpCode::DefineLocal | OpCode::Call | OpCode::PushScope => 1,
+            OpCode::Class => 4,
+            _ => 0
+        }
+    }
+}
+
+// A compiled function body. The script itself is a function with no parameters.
+// Parameters past `required` have defaults, filled in by code at the start of the body.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub required: usize,
+    pub locals: usize,
+    pub initializer: bool,
+    pub chunk: Chunk
+}
+
+#[derive(Debug)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<LoxType>,
+    pub functions: Vec<Rc<Function>>,
+    // Run-length encoded: (source span, number of bytes compiled from it)
+    spans: Vec<(Span,usize)>
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Chunk {
+            code: Vec::new(),
+            constants: Vec::new(),
+            functions: Vec::new(),
+            spans: Vec::new()
+        }
+    }
+
+    pub fn write(&mut self,byte: u8,span: Span) {
+        self.code.push(byte);
+        let same_span = match self.spans.last() {
+            Some(&(s,_)) => s == span,
+            None => false
+        };
+
+        if same_span {
+            if let Some(last) = self.spans.last_mut() {
+                last.1 += 1;
+            }
+        } else {
+            self.spans.push((span,1));
+        }
+    }
+
+    pub fn add_constant(&mut self,value: LoxType) -> usize {
+        // Names get looked up over and over, so don't store them twice
+        if let LoxType::String(_) = value {
+            if let Some(i) = self.constants.iter().position(|c| c == &value) {
+                return i;
+            }
+        }
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    pub fn add_function(&mut self,function: Function) -> usize {
+        self.functions.push(Rc::new(function));
+        self.functions.len() - 1
+    }
+
+    pub fn span(&self,offset: usize) -> Span {
+        let mut seen = 0;
+        for &(span,count) in &self.spans {
+            seen += count;
+            if offset < seen {
+                return span;
+            }
+        }
+        Span::default()
+    }
+
+    pub fn line(&self,offset: usize) -> i32 {
+        self.span(offset).line
+    }
+
+    pub fn read_u16(&self,offset: usize) -> u16 {
+        ((self.code[offset] as u16) << 8) | (self.code[offset + 1] as u16)
+    }
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.code.len() {
+            let op = match OpCode::from_byte(self.code[offset]) {
+                Some(op) => op,
+                None => {
+                    writeln!(f,"{:04} ???? {}",offset,self.code[offset])?;
+                    offset += 1;
+                    continue;
+                }
+            };
+
+            write!(f,"{:04} {:4} {:?}",offset,self.line(offset),op)?;
+            match op {
+                OpCode::Constant | OpCode::GetGlobal | OpCode::SetGlobal | OpCode::DefineGlobal |
+                OpCode::GetProperty | OpCode::SetProperty | OpCode::GetSuper | OpCode::Import => {
+                    let c = self.read_u16(offset + 1) as usize;
+                    write!(f," {} ({:?})",c,self.constants.get(c))?;
+                },
+                OpCode::Jump | OpCode::JumpIfFalse | OpCode::PushCatch | OpCode::PushFinally => {
+                    write!(f," -> {}",offset + 3 + self.read_u16(offset + 1) as usize)?;
+                },
+                OpCode::JumpIfPassed => {
+                    write!(f," {} -> {}",self.code[offset + 1],offset + 4 + self.read_u16(offset + 2) as usize)?;
+                },
+                OpCode::Loop => {
+                    write!(f," -> {}",(offset + 3).saturating_sub(self.read_u16(offset + 1) as usize))?;
+                },
+                OpCode::Closure => {
+                    let c = self.read_u16(offset + 1) as usize;
+                    if let Some(fun) = self.functions.get(c) {
+                        write!(f," <fn {}>",fun.name)?;
+                    }
+                },
+                OpCode::GetLocal | OpCode::SetLocal => {
+                    write!(f," {} {}",self.code[offset + 1],self.code[offset + 2])?;
+                },
+                OpCode::DefineLocal | OpCode::Call | OpCode::PushScope => {
+                    write!(f," {}",self.code[offset + 1])?;
+                },
+                OpCode::List | OpCode::Map => {
+                    write!(f," {}",self.read_u16(offset + 1))?;
+                },
+                OpCode::Class => {
+                    let c = self.read_u16(offset + 1) as usize;
+                    write!(f," {:?} methods={} super={}",self.constants.get(c),self.code[offset + 3],self.code[offset + 4])?;
+                },
+                _ => ()
+            }
+            writeln!(f,"")?;
+            offset += 1 + op.operands();
+        }
+
+        for fun in &self.functions {
+            writeln!(f,"\n<fn {}>",fun.name)?;
+            write!(f,"{}",fun.chunk)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,752 @@
+use super::super::*;
+use super::chunk::{Chunk,Function,OpCode};
+use std::rc::Rc;
+use std::mem;
+
+// Compiles the AST into bytecode. Scopes are laid out exactly like the tree-walker's
+// environments (one per block, one per call, one for 'this' and one for 'super'), so
+// locals become a (depth,slot) pair instead of a name.
+pub struct Compiler {
+    chunk: Chunk,
+    scopes: Vec<Vec<String>>,
+    initializer: bool,
+    span: Span,
+    // How deep expression() has recursed
+    depth: usize,
+    loops: Vec<Loop>,
+    guards: Vec<Guard>
+}
+
+// A loop being compiled, for break and continue to jump out of
+struct Loop {
+    // How many scopes were open outside it
+    depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>
+}
+
+// A try or catch block with a handler installed. Jumping out of one removes the handler and
+// runs the finally block in line, since nothing at runtime tracks where the jump was headed.
+struct Guard {
+    // How many scopes and loops were open outside it
+    depth: usize,
+    loops: usize,
+    finally: Option<Rc<Statement>>
+}
+
+type CompileResult = Result<(),LoxError>;
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler {
+            chunk: Chunk::new(),
+            scopes: Vec::new(),
+            initializer: false,
+            span: Span::default(),
+            depth: 0,
+            loops: Vec::new(),
+            guards: Vec::new()
+        }
+    }
+
+    pub fn compile(self,statements: &Vec<Rc<Statement>>) -> Result<Function,LoxError> {
+        self.script(statements,None)
+    }
+
+    // A script that ends by handing back the value of `result` instead of nil
+    pub fn compile_returning(self,statements: &[Rc<Statement>],result: &Expr) -> Result<Function,LoxError> {
+        self.script(statements,Some(result))
+    }
+
+    fn script(mut self,statements: &[Rc<Statement>],result: Option<&Expr>) -> Result<Function,LoxError> {
+        for s in statements {
+            self.statement(s).map_err(|e| e.of_kind(ErrorKind::Syntax))?;
+        }
+        match result {
+            Some(e) => self.expression(e).map_err(|e| e.of_kind(ErrorKind::Syntax))?,
+            None => self.emit(OpCode::Nil)
+        }
+        self.emit(OpCode::Return);
+
+        Ok(Function {
+            name: "script".to_string(),
+            arity: 0,
+            required: 0,
+            locals: 0,
+            initializer: false,
+            chunk: self.chunk
+        })
+    }
+
+    // Emitting helpers
+
+    fn emit(&mut self,op: OpCode) {
+        let span = self.span;
+        self.chunk.write(op as u8,span);
+    }
+
+    fn emit_byte(&mut self,byte: u8) {
+        let span = self.span;
+        self.chunk.write(byte,span);
+    }
+
+    fn emit_u16(&mut self,value: u16) {
+        self.emit_byte((value >> 8) as u8);
+        self.emit_byte((value & 0xff) as u8);
+    }
+
+    fn emit_jump(&mut self,op: OpCode) -> usize {
+        self.emit(op);
+        self.emit_jump_operand()
+    }
+
+    // Placeholder offset for patch_jump, for jumps with other operands before it
+    fn emit_jump_operand(&mut self) -> usize {
+        self.emit_u16(0xffff);
+        self.chunk.code.len() - 2
+    }
+
+    fn patch_jump(&mut self,offset: usize) -> CompileResult {
+        let jump = self.chunk.code.len() - offset - 2;
+        if jump > u16::max_value() as usize {
+            return Err(LoxError::with_span("Too much code to jump over".to_string(),self.span));
+        }
+        self.chunk.code[offset] = (jump >> 8) as u8;
+        self.chunk.code[offset + 1] = (jump & 0xff) as u8;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self,start: usize) -> CompileResult {
+        self.emit(OpCode::Loop);
+        let offset = self.chunk.code.len() - start + 2;
+        if offset > u16::max_value() as usize {
+            return Err(LoxError::with_span("Loop body too large".to_string(),self.span));
+        }
+        self.emit_u16(offset as u16);
+        Ok(())
+    }
+
+    fn constant(&mut self,value: LoxType) -> Result<u16,LoxError> {
+        let c = self.chunk.add_constant(value);
+        if c > u16::max_value() as usize {
+            return Err(LoxError::with_span("Too many constants in one chunk".to_string(),self.span));
+        }
+        Ok(c as u16)
+    }
+
+    fn emit_constant(&mut self,op: OpCode,value: LoxType) -> CompileResult {
+        let c = self.constant(value)?;
+        self.emit(op);
+        self.emit_u16(c);
+        Ok(())
+    }
+
+    // Scopes
+
+    fn begin_scope(&mut self) -> usize {
+        self.scopes.push(Vec::new());
+        self.emit(OpCode::PushScope);
+        self.emit_byte(0);
+        self.chunk.code.len() - 1
+    }
+
+    fn end_scope(&mut self,size_offset: usize) -> CompileResult {
+        let size = self.scopes.pop().map(|s| s.len()).unwrap_or(0);
+        if size > u8::max_value() as usize {
+            return Err(LoxError::with_span("Too many local variables in one scope".to_string(),self.span));
+        }
+        self.chunk.code[size_offset] = size as u8;
+        self.emit(OpCode::PopScope);
+        Ok(())
+    }
+
+    fn declare(&mut self,name: &str) -> Option<u8> {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.push(name.to_string());
+                Some((scope.len() - 1) as u8)
+            },
+            None => None
+        }
+    }
+
+    fn resolve(&self,name: &str) -> Option<(u8,u8)> {
+        for (depth,scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(slot) = scope.iter().rposition(|n| n == name) {
+                return Some((depth as u8,slot as u8));
+            }
+        }
+        None
+    }
+
+    fn define(&mut self,name: &str) -> CompileResult {
+        match self.declare(name) {
+            Some(slot) => {
+                self.emit(OpCode::DefineLocal);
+                self.emit_byte(slot);
+                Ok(())
+            },
+            None => self.emit_constant(OpCode::DefineGlobal,LoxType::String(name.to_string()))
+        }
+    }
+
+    fn get_variable(&mut self,name: &str) -> CompileResult {
+        match self.resolve(name) {
+            Some((depth,slot)) => {
+                self.emit(OpCode::GetLocal);
+                self.emit_byte(depth);
+                self.emit_byte(slot);
+                Ok(())
+            },
+            None => self.emit_constant(OpCode::GetGlobal,LoxType::String(name.to_string()))
+        }
+    }
+
+    fn set_variable(&mut self,name: &str) -> CompileResult {
+        match self.resolve(name) {
+            Some((depth,slot)) => {
+                self.emit(OpCode::SetLocal);
+                self.emit_byte(depth);
+                self.emit_byte(slot);
+                Ok(())
+            },
+            None => self.emit_constant(OpCode::SetGlobal,LoxType::String(name.to_string()))
+        }
+    }
+
+    // Leaves the guards from `first` on, innermost first, for a jump out of them. Returns how
+    // many scopes are still open afterwards; the compile-time scopes are left as they were, for
+    // whatever follows the jump.
+    fn leave_guards(&mut self,first: usize) -> Result<usize,LoxError> {
+        let scopes = self.scopes.clone();
+        let span = self.span;
+        let guards = self.guards[first..].iter().rev().map(|g| (g.depth,g.finally.clone())).collect::<Vec<_>>();
+        let mut res = Ok(());
+        for (depth,finally) in guards {
+            while self.scopes.len() > depth {
+                self.emit(OpCode::PopScope);
+                self.scopes.pop();
+            }
+            self.emit(OpCode::PopHandler);
+            if let Some(f) = finally {
+                res = self.statement(&f);
+                self.span = span;
+                if res.is_err() {
+                    break;
+                }
+            }
+        }
+        let open = self.scopes.len();
+        self.scopes = scopes;
+        res.map(|_| open)
+    }
+
+    // Statements
+
+    fn statement(&mut self,s: &Statement) -> CompileResult {
+        match s {
+            &Statement::Expression(ref e) => {
+                self.expression(e)?;
+                self.emit(OpCode::Pop);
+            },
+            &Statement::Variable(ref name,ref init) => {
+                self.span = name.span;
+                match init {
+                    &Some(ref i) => self.expression(i)?,
+                    &None => self.emit(OpCode::Nil)
+                }
+                self.span = name.span;
+                self.define(&name.lexeme)?;
+            },
+            &Statement::Block(ref statements) => {
+                let size = self.begin_scope();
+                for s in statements {
+                    self.statement(s)?;
+                }
+                self.end_scope(size)?;
+            },
+            &Statement::If(ref cond,ref then,ref or) => {
+                self.expression(cond)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.statement(then)?;
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit(OpCode::Pop);
+                if let &Some(ref or_branch) = or {
+                    self.statement(or_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            },
+            &Statement::While(ref cond,ref body,ref increment) => {
+                let start = self.chunk.code.len();
+                self.expression(cond)?;
+                let exit = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+
+                self.loops.push(Loop { depth: self.scopes.len(), breaks: Vec::new(), continues: Vec::new() });
+                let res = self.statement(body);
+                let current = self.loops.pop();
+                res?;
+
+                let (breaks,continues) = match current {
+                    Some(l) => (l.breaks,l.continues),
+                    None => (Vec::new(),Vec::new())
+                };
+                for c in continues {
+                    self.patch_jump(c)?;
+                }
+                if let &Some(ref i) = increment {
+                    self.expression(i)?;
+                    self.emit(OpCode::Pop);
+                }
+                self.emit_loop(start)?;
+                self.patch_jump(exit)?;
+                self.emit(OpCode::Pop);
+                // Breaks skip the Pop, since they leave no condition on the stack
+                for b in breaks {
+                    self.patch_jump(b)?;
+                }
+            },
+            &Statement::Break(ref keyword) | &Statement::Continue(ref keyword) => {
+                self.span = keyword.span;
+                let depth = match self.loops.last() {
+                    Some(l) => l.depth,
+                    None => return Err(LoxError::at(format!("Can't use '{}' outside of a loop",keyword.lexeme),keyword))
+                };
+                let target = self.loops.len() - 1;
+                let first = self.guards.iter().position(|g| g.loops > target).unwrap_or(self.guards.len());
+                let open = self.leave_guards(first)?;
+                // Leave every block opened inside the loop
+                for _ in depth..open {
+                    self.emit(OpCode::PopScope);
+                }
+                let jump = self.emit_jump(OpCode::Jump);
+                if let Some(l) = self.loops.last_mut() {
+                    match keyword.token {
+                        TokenType::Break => l.breaks.push(jump),
+                        _ => l.continues.push(jump)
+                    }
+                }
+            },
+            &Statement::Function(ref name,_,_) => {
+                self.span = name.span;
+                // Declared first so the body can call itself
+                let slot = self.declare(&name.lexeme);
+                self.function(s,false)?;
+                self.span = name.span;
+                match slot {
+                    Some(slot) => {
+                        self.emit(OpCode::DefineLocal);
+                        self.emit_byte(slot);
+                    },
+                    None => self.emit_constant(OpCode::DefineGlobal,LoxType::String(name.lexeme.clone()))?
+                }
+            },
+            &Statement::Return(ref t,ref e) => {
+                if self.initializer {
+                    // init() always hands back the instance
+                    self.span = t.span;
+                    self.get_variable("this")?;
+                } else {
+                    self.expression(e)?;
+                    self.span = t.span;
+                }
+                // The frame's scopes go with it, but finally blocks still have to run
+                self.leave_guards(0)?;
+                self.emit(OpCode::Return);
+            },
+            &Statement::Throw(ref keyword,ref e) => {
+                self.expression(e)?;
+                self.span = keyword.span;
+                self.emit(OpCode::Throw);
+            },
+            &Statement::Try(ref body,ref catch,ref finally) => self.try_statement(body,catch,finally)?,
+            &Statement::Import(ref t,ref imports) => {
+                let path = t.literal.clone().unwrap_or(LoxType::Nil);
+                match imports {
+                    &Imports::All => {
+                        self.span = t.span;
+                        self.emit_constant(OpCode::Import,path)?;
+                        self.emit(OpCode::ImportAll);
+                    },
+                    &Imports::Module(ref name) => {
+                        self.span = t.span;
+                        self.emit_constant(OpCode::Import,path)?;
+                        self.span = name.span;
+                        self.define(&name.lexeme)?;
+                    },
+                    // Every name is looked up before any is bound. The module is cached after the
+                    // first Import, so loading it per name is cheap.
+                    &Imports::Names(ref names) => {
+                        for name in names {
+                            self.span = t.span;
+                            self.emit_constant(OpCode::Import,path.clone())?;
+                            self.span = name.span;
+                            self.emit_constant(OpCode::GetProperty,LoxType::String(name.lexeme.clone()))?;
+                        }
+                        for name in names.iter().rev() {
+                            self.span = name.span;
+                            self.define(&name.lexeme)?;
+                        }
+                    }
+                }
+            },
+            &Statement::Class(ref name,ref superclass,ref methods) => {
+                self.span = name.span;
+                if methods.len() > u8::max_value() as usize {
+                    return Err(LoxError::at("Too many methods in one class".to_string(),name));
+                }
+
+                let slot = self.declare(&name.lexeme);
+                let super_scope = match superclass {
+                    &Some(ref e) => {
+                        self.expression(e)?;
+                        self.span = name.span;
+                        self.emit(OpCode::Inherit);
+                        let size = self.begin_scope();
+                        self.define("super")?;
+                        self.get_variable("super")?;
+                        Some(size)
+                    },
+                    &None => None
+                };
+
+                // Bound methods get a frame holding 'this' when they're looked up
+                self.scopes.push(vec!["this".to_string()]);
+                for m in methods {
+                    if let &Statement::Function(ref t,_,_) = m.as_ref() {
+                        self.span = t.span;
+                        self.emit_constant(OpCode::Constant,LoxType::String(t.lexeme.clone()))?;
+                        self.function(m,t.lexeme == "init")?;
+                    }
+                }
+                self.scopes.pop();
+
+                self.span = name.span;
+                let c = self.constant(LoxType::String(name.lexeme.clone()))?;
+                self.emit(OpCode::Class);
+                self.emit_u16(c);
+                self.emit_byte(methods.len() as u8);
+                self.emit_byte(if super_scope.is_some() { 1 } else { 0 });
+
+                if let Some(size) = super_scope {
+                    // Keep the class on the stack while the 'super' frame goes away
+                    self.end_scope(size)?;
+                }
+
+                match slot {
+                    Some(slot) => {
+                        self.emit(OpCode::DefineLocal);
+                        self.emit_byte(slot);
+                    },
+                    None => self.emit_constant(OpCode::DefineGlobal,LoxType::String(name.lexeme.clone()))?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The try block runs under a handler that goes to the catch block, or with no catch to the
+    // finally block and a rethrow. A catch followed by a finally gets a handler of its own, so
+    // the finally still runs when the catch block fails.
+    fn try_statement(&mut self,body: &Rc<Statement>,catch: &Option<Catch>,finally: &Option<Rc<Statement>>) -> CompileResult {
+        let handler = self.emit_jump(if catch.is_some() { OpCode::PushCatch } else { OpCode::PushFinally });
+        self.guarded(body,finally)?;
+        self.emit(OpCode::PopHandler);
+        if let &Some(ref f) = finally {
+            self.statement(f)?;
+        }
+        let end = self.emit_jump(OpCode::Jump);
+        self.patch_jump(handler)?;
+
+        match (catch,finally) {
+            // The caught value is on the stack
+            (&Some(ref c),&Some(ref f)) => {
+                let depth = self.scopes.len();
+                let size = self.begin_scope();
+                self.span = c.name.span;
+                self.define(&c.name.lexeme)?;
+                let rethrow = self.emit_jump(OpCode::PushFinally);
+                self.guards.push(Guard { depth: depth, loops: self.loops.len(), finally: finally.clone() });
+                let res = self.statement(&c.body);
+                self.guards.pop();
+                res?;
+                self.emit(OpCode::PopHandler);
+                self.end_scope(size)?;
+                self.statement(f)?;
+                let done = self.emit_jump(OpCode::Jump);
+                self.patch_jump(rethrow)?;
+                // The handler comes back inside the catch block's scope
+                self.emit(OpCode::PopScope);
+                self.statement(f)?;
+                self.emit(OpCode::Rethrow);
+                self.patch_jump(done)?;
+            },
+            (&Some(ref c),&None) => {
+                let size = self.begin_scope();
+                self.span = c.name.span;
+                self.define(&c.name.lexeme)?;
+                self.statement(&c.body)?;
+                self.end_scope(size)?;
+            },
+            (&None,&Some(ref f)) => {
+                self.statement(f)?;
+                self.emit(OpCode::Rethrow);
+            },
+            (&None,&None) => ()
+        }
+        self.patch_jump(end)
+    }
+
+    // Compiles a block with a handler installed, so jumps out of it know to leave the handler
+    fn guarded(&mut self,body: &Statement,finally: &Option<Rc<Statement>>) -> CompileResult {
+        self.guards.push(Guard { depth: self.scopes.len(), loops: self.loops.len(), finally: finally.clone() });
+        let res = self.statement(body);
+        self.guards.pop();
+        res
+    }
+
+    fn function(&mut self,declaration: &Statement,initializer: bool) -> CompileResult {
+        let (name,params,body) = match declaration {
+            &Statement::Function(ref name,ref params,ref body) => (name,params,body),
+            _ => return Err(LoxError::with_span("Expected a function declaration".to_string(),self.span))
+        };
+
+        if params.len() > u8::max_value() as usize {
+            return Err(LoxError::at("Too many parameters".to_string(),name));
+        }
+
+        let enclosing = mem::replace(&mut self.chunk,Chunk::new());
+        let enclosing_initializer = mem::replace(&mut self.initializer,initializer);
+        let enclosing_loops = mem::replace(&mut self.loops,Vec::new());
+        let enclosing_guards = mem::replace(&mut self.guards,Vec::new());
+        self.scopes.push(Vec::new());
+
+        let res = self.parameters(params).and_then(|()| self.function_body(body));
+
+        let locals = self.scopes.pop().map(|s| s.len()).unwrap_or(0);
+        let chunk = mem::replace(&mut self.chunk,enclosing);
+        self.initializer = enclosing_initializer;
+        self.loops = enclosing_loops;
+        self.guards = enclosing_guards;
+        res?;
+
+        if locals > u8::max_value() as usize {
+            return Err(LoxError::at("Too many local variables in one function".to_string(),name));
+        }
+
+        let index = self.chunk.add_function(Function {
+            name: name.lexeme.clone(),
+            arity: params.len(),
+            required: params.iter().filter(|p| p.default.is_none()).count(),
+            locals: locals,
+            initializer: initializer,
+            chunk: chunk
+        });
+
+        self.span = name.span;
+        self.emit(OpCode::Closure);
+        self.emit_u16(index as u16);
+        Ok(())
+    }
+
+    // Declares the parameters in order, so each default only sees the ones before it, and fills
+    // in the defaults of any that weren't passed
+    fn parameters(&mut self,params: &Vec<Parameter>) -> CompileResult {
+        for (slot,p) in params.iter().enumerate() {
+            if let Some(ref default) = p.default {
+                self.span = p.name.span;
+                self.emit(OpCode::JumpIfPassed);
+                self.emit_byte(slot as u8);
+                let passed = self.emit_jump_operand();
+                self.expression(default)?;
+                self.span = p.name.span;
+                self.emit(OpCode::SetLocal);
+                self.emit_byte(0);
+                self.emit_byte(slot as u8);
+                self.emit(OpCode::Pop);
+                self.patch_jump(passed)?;
+            }
+            self.declare(&p.name.lexeme);
+        }
+        Ok(())
+    }
+
+    fn function_body(&mut self,body: &Statement) -> CompileResult {
+        // The body shares the parameters' scope, same as LoxFun::call
+        if let &Statement::Block(ref statements) = body {
+            for s in statements {
+                self.statement(s)?;
+            }
+        }
+
+        if self.initializer {
+            self.get_variable("this")?;
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.emit(OpCode::Return);
+        Ok(())
+    }
+
+    // Expressions
+
+    fn expression(&mut self,e: &Expr) -> CompileResult {
+        // The parser caps how deeply source nests, but postfix chains like a.b.c() still build
+        // trees as deep as they are long
+        if self.depth >= MAX_NESTING {
+            return Err(LoxError::with_span("Too much nesting".to_string(),self.span));
+        }
+        self.depth += 1;
+        let res = self.compile_expr(e);
+        self.depth -= 1;
+        res
+    }
+
+    fn compile_expr(&mut self,e: &Expr) -> CompileResult {
+        match e {
+            &Expr::Literal(ref l) => {
+                match l {
+                    &LoxType::Nil => self.emit(OpCode::Nil),
+                    &LoxType::Boolean(true) => self.emit(OpCode::True),
+                    &LoxType::Boolean(false) => self.emit(OpCode::False),
+                    _ => self.emit_constant(OpCode::Constant,l.clone())?
+                }
+            },
+            &Expr::Grouping(ref e) => self.expression(e)?,
+            &Expr::Variable(ref t,_) => {
+                self.span = t.span;
+                self.get_variable(&t.lexeme)?;
+            },
+            &Expr::Assign(ref t,ref value,_) => {
+                self.expression(value)?;
+                self.span = t.span;
+                self.set_variable(&t.lexeme)?;
+            },
+            &Expr::Unary(ref op,ref e) => {
+                self.expression(e)?;
+                self.span = op.span;
+                match op.token {
+                    TokenType::Minus => self.emit(OpCode::Negate),
+                    TokenType::Bang => self.emit(OpCode::Not),
+                    _ => return Err(LoxError::at("Invalid unary operator".to_string(),op))
+                }
+            },
+            &Expr::Binary(..) => {
+                let (first,links) = e.chain();
+                self.expression(first)?;
+                for (op,right) in links {
+                    self.expression(right)?;
+                    self.span = op.span;
+                    let code = match op.token {
+                        TokenType::Minus => OpCode::Subtract,
+                        TokenType::Slash => OpCode::Divide,
+                        TokenType::Star => OpCode::Multiply,
+                        TokenType::Plus => OpCode::Add,
+                        TokenType::Greater => OpCode::Greater,
+                        TokenType::GreaterEqual => OpCode::GreaterEqual,
+                        TokenType::Less => OpCode::Less,
+                        TokenType::LessEqual => OpCode::LessEqual,
+                        TokenType::BangEqual => OpCode::NotEqual,
+                        TokenType::EqualEqual => OpCode::Equal,
+                        _ => return Err(LoxError::at("Invalid binary operator".to_string(),op))
+                    };
+                    self.emit(code);
+                }
+            },
+            &Expr::Logical(..) => {
+                let (first,links) = e.chain();
+                self.expression(first)?;
+                for (op,right) in links {
+                    self.span = op.span;
+                    if op.token == TokenType::Or {
+                        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        let end_jump = self.emit_jump(OpCode::Jump);
+                        self.patch_jump(else_jump)?;
+                        self.emit(OpCode::Pop);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump)?;
+                    } else {
+                        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        self.emit(OpCode::Pop);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump)?;
+                    }
+                }
+            },
+            &Expr::Call(ref callee,ref paren,ref args) => {
+                self.expression(callee)?;
+                if args.len() > u8::max_value() as usize {
+                    return Err(LoxError::at("Too many arguments".to_string(),paren));
+                }
+                for a in args {
+                    self.expression(a)?;
+                }
+                self.span = paren.span;
+                self.emit(OpCode::Call);
+                self.emit_byte(args.len() as u8);
+            },
+            &Expr::Get(ref object,ref name) => {
+                self.expression(object)?;
+                self.span = name.span;
+                self.emit_constant(OpCode::GetProperty,LoxType::String(name.lexeme.clone()))?;
+            },
+            &Expr::Set(ref object,ref name,ref value) => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.span = name.span;
+                self.emit_constant(OpCode::SetProperty,LoxType::String(name.lexeme.clone()))?;
+            },
+            &Expr::List(ref bracket,ref elements) => {
+                if elements.len() > u16::max_value() as usize {
+                    return Err(LoxError::at("Too many elements in one list".to_string(),bracket));
+                }
+                for e in elements {
+                    self.expression(e)?;
+                }
+                self.span = bracket.span;
+                self.emit(OpCode::List);
+                self.emit_u16(elements.len() as u16);
+            },
+            &Expr::Map(ref brace,ref entries) => {
+                if entries.len() > u16::max_value() as usize {
+                    return Err(LoxError::at("Too many entries in one map".to_string(),brace));
+                }
+                for &(ref k,ref v) in entries {
+                    self.expression(k)?;
+                    self.expression(v)?;
+                }
+                self.span = brace.span;
+                self.emit(OpCode::Map);
+                self.emit_u16(entries.len() as u16);
+            },
+            &Expr::Index(ref object,ref bracket,ref index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.span = bracket.span;
+                self.emit(OpCode::GetIndex);
+            },
+            &Expr::SetIndex(ref object,ref bracket,ref index,ref value) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.span = bracket.span;
+                self.emit(OpCode::SetIndex);
+            },
+            &Expr::Lambda(ref declaration) => self.function(declaration,false)?,
+            &Expr::This(ref keyword,_) => {
+                self.span = keyword.span;
+                self.get_variable("this")?;
+            },
+            &Expr::Super(ref keyword,ref method,_) => {
+                self.span = keyword.span;
+                self.get_variable("super")?;
+                self.get_variable("this")?;
+                self.span = method.span;
+                self.emit_constant(OpCode::GetSuper,LoxType::String(method.lexeme.clone()))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,573 @@
+use super::super::*;
+use super::chunk::{Function,OpCode};
+use interpreter::Interpreter;
+use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::fmt;
+
+// Locals live in heap frames chained like Environment, addressed by slot instead of name.
+#[derive(Debug)]
+pub struct Scope {
+    slots: Vec<LoxType>,
+    parent: Option<Rc<RefCell<Scope>>>
+}
+
+impl Scope {
+    fn new(slots: Vec<LoxType>,parent: Option<Rc<RefCell<Scope>>>) -> Rc<RefCell<Scope>> {
+        Rc::new(RefCell::new(Scope {
+            slots: slots,
+            parent: parent
+        }))
+    }
+}
+
+#[derive(Clone)]
+pub struct Closure {
+    function: Rc<Function>,
+    scope: Option<Rc<RefCell<Scope>>>
+}
+
+impl Closure {
+    pub fn new(function: Rc<Function>,scope: Option<Rc<RefCell<Scope>>>) -> Closure {
+        Closure {
+            function: function,
+            scope: scope
+        }
+    }
+}
+
+impl Callable for Closure {
+    fn arity(&self) -> Arity {
+        Arity::range(self.function.required,self.function.arity)
+    }
+
+    fn name(&self) -> String {
+        self.function.name.clone()
+    }
+
+    fn call(&self,i: &mut Interpreter,arguments: Vec<LoxType>) -> Result<LoxType,LoxError> {
+        let argc = arguments.len();
+        let mut slots = arguments;
+        slots.resize(self.function.locals,LoxType::Nil);
+        let scope = Scope::new(slots,self.scope.clone());
+        Frame::new(i,&self.function,Some(scope),argc).run()
+    }
+
+    fn bind(&self,instance: LoxType) -> Box<Callable> {
+        Box::new(Closure::new(self.function.clone(),Some(Scope::new(vec![instance],self.scope.clone()))))
+    }
+
+    fn box_clone(&self) -> Box<Callable> {
+        Box::new((*self).clone())
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"<function {}>",self.function.name)
+    }
+}
+
+// One activation of a function: its instruction pointer, operand stack and innermost scope.
+// Calls into other closures run in a fresh Frame through Callable::call.
+struct Frame<'a> {
+    host: &'a mut Interpreter,
+    function: &'a Function,
+    ip: usize,
+    stack: Vec<LoxType>,
+    scope: Option<Rc<RefCell<Scope>>>,
+    // How many arguments the call passed, for JumpIfPassed
+    argc: usize,
+    handlers: Vec<Handler>,
+    // Errors held while a finally block runs, for Rethrow to raise again
+    pending: Vec<LoxError>
+}
+
+// Where an error raised inside a try goes. A catch gets the error's value on the stack; a
+// finally gets it set aside in `pending`.
+struct Handler {
+    target: usize,
+    stack: usize,
+    scope: Option<Rc<RefCell<Scope>>>,
+    pending: usize,
+    finally: bool
+}
+
+impl<'a> Frame<'a> {
+    fn new(host: &'a mut Interpreter,function: &'a Function,scope: Option<Rc<RefCell<Scope>>>,argc: usize) -> Frame<'a> {
+        Frame {
+            host: host,
+            function: function,
+            ip: 0,
+            stack: Vec::new(),
+            scope: scope,
+            argc: argc,
+            handlers: Vec::new(),
+            pending: Vec::new()
+        }
+    }
+
+    // Span of the instruction being executed
+    fn span(&self) -> Span {
+        self.function.chunk.span(self.ip.saturating_sub(1))
+    }
+
+    fn error(&self,msg: &str) -> LoxError {
+        LoxError::with_span(msg.to_string(),self.span())
+    }
+
+    fn internal(&self,msg: &str) -> LoxError {
+        self.error(msg).of_kind(ErrorKind::Internal)
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let b = self.function.chunk.code[self.ip];
+        self.ip += 1;
+        b
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let v = self.function.chunk.read_u16(self.ip);
+        self.ip += 2;
+        v
+    }
+
+    fn read_name(&mut self) -> Result<String,LoxError> {
+        let c = self.read_u16() as usize;
+        match self.function.chunk.constants.get(c) {
+            Some(&LoxType::String(ref s)) => Ok(s.clone()),
+            _ => Err(self.internal("Invalid constant"))
+        }
+    }
+
+    fn pop(&mut self) -> Result<LoxType,LoxError> {
+        match self.stack.pop() {
+            Some(v) => Ok(v),
+            None => Err(self.internal("Stack underflow"))
+        }
+    }
+
+    fn peek(&self,distance: usize) -> Result<&LoxType,LoxError> {
+        if distance < self.stack.len() {
+            Ok(&self.stack[self.stack.len() - 1 - distance])
+        } else {
+            Err(self.internal("Stack underflow"))
+        }
+    }
+
+    fn ancestor(&self,depth: usize) -> Result<Rc<RefCell<Scope>>,LoxError> {
+        let mut current = self.scope.clone();
+        for _ in 0..depth {
+            current = match current {
+                Some(s) => s.borrow().parent.clone(),
+                None => None
+            };
+        }
+
+        match current {
+            Some(s) => Ok(s),
+            None => Err(self.error("Variable not found").of_kind(ErrorKind::UndefinedVariable))
+        }
+    }
+
+    fn binary(&mut self,op: OpCode) -> Result<(),LoxError> {
+        let right = self.pop()?;
+        let left = self.pop()?;
+        let result = match op {
+            OpCode::Subtract | OpCode::Divide | OpCode::Multiply => {
+                let (l,r) = match (left,right) {
+                    (LoxType::Number(l),LoxType::Number(r)) => (l,r),
+                    _ => return Err(self.error("Operands must be numbers.").of_kind(ErrorKind::Type))
+                };
+                LoxType::Number(match op {
+                    OpCode::Subtract => l - r,
+                    OpCode::Divide => l / r,
+                    _ => l * r
+                })
+            },
+            OpCode::Add => {
+                match (&left,&right) {
+                    (&LoxType::Number(l),&LoxType::Number(r)) => LoxType::Number(l + r),
+                    (&LoxType::String(_),_) | (_,&LoxType::String(_)) => LoxType::String(format!("{}{}",left,right)),
+                    _ => return Err(self.error("Can't add two different types").of_kind(ErrorKind::Type))
+                }
+            },
+            OpCode::Greater | OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual => {
+                let ordering = left.ordering(&right).map_err(|e| e.located(self.span()))?;
+                LoxType::Boolean(match (op,ordering) {
+                    (OpCode::Greater,Some(Ordering::Greater)) | (OpCode::GreaterEqual,Some(Ordering::Greater)) => true,
+                    (OpCode::Less,Some(Ordering::Less)) | (OpCode::LessEqual,Some(Ordering::Less)) => true,
+                    (OpCode::GreaterEqual,Some(Ordering::Equal)) | (OpCode::LessEqual,Some(Ordering::Equal)) => true,
+                    _ => false
+                })
+            },
+            OpCode::Equal => LoxType::Boolean(left == right),
+            OpCode::NotEqual => LoxType::Boolean(left != right),
+            _ => return Err(self.internal("Invalid binary operator"))
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn call(&mut self,argc: usize) -> Result<(),LoxError> {
+        let start = match self.stack.len().checked_sub(argc + 1) {
+            Some(s) => s,
+            None => return Err(self.internal("Stack underflow"))
+        };
+        let arguments = self.stack.split_off(start + 1);
+        let callee = self.pop()?;
+
+        let fun: Box<Callable> = match callee {
+            LoxType::Callable(c) => c,
+            LoxType::Class(c) => Box::new(c),
+            _ => return Err(self.error("Expected callable object but got normal type").of_kind(ErrorKind::Type))
+        };
+
+        if !fun.arity().accepts(arguments.len()) {
+            return Err(self.error(&fun.arity().mismatch(arguments.len())).of_kind(ErrorKind::Arity));
+        }
+
+        let span = self.span();
+        let result = self.host.invoke(fun.as_ref(),arguments,Some(span))?;
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn class(&mut self,name: String,methods: usize,has_superclass: bool) -> Result<(),LoxError> {
+        // Each method sits on the stack as its name followed by its closure
+        let start = match self.stack.len().checked_sub(methods * 2) {
+            Some(s) => s,
+            None => return Err(self.internal("Stack underflow"))
+        };
+
+        let mut table: HashMap<String,Box<Callable>> = HashMap::new();
+        let mut entries = self.stack.split_off(start).into_iter();
+        while let (Some(name),Some(method)) = (entries.next(),entries.next()) {
+            if let (LoxType::String(name),LoxType::Callable(method)) = (name,method) {
+                table.insert(name,method);
+            }
+        }
+
+        let superclass = if has_superclass {
+            match self.pop()? {
+                LoxType::Class(c) => Some(c),
+                _ => return Err(self.error("Superclass must be a class").of_kind(ErrorKind::Type))
+            }
+        } else {
+            None
+        };
+
+        self.stack.push(LoxType::Class(Rc::new(LoxClass::new(name,superclass,table))));
+        Ok(())
+    }
+
+    fn import(&mut self,path: LoxType) -> Result<Rc<LoxModule>,LoxError> {
+        // Modules run on the tree-walker and are cached by the shared host
+        let token = Token {
+            token: TokenType::String,
+            lexeme: format!("{}",path),
+            line: self.span().line,
+            literal: Some(path),
+            span: self.span()
+        };
+
+        self.host.load(&token)
+    }
+
+    fn run(&mut self) -> Result<LoxType,LoxError> {
+        loop {
+            match self.step() {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => (),
+                Err(e) => self.unwind(e)?
+            }
+        }
+    }
+
+    // Hands an error to the innermost handler in this frame: the stack and scope go back to how
+    // they were when it was installed and execution carries on at its target. Without one, or
+    // when the error means the VM itself is broken, the error leaves the frame.
+    fn unwind(&mut self,e: LoxError) -> Result<(),LoxError> {
+        if !e.catchable() {
+            return Err(e);
+        }
+        let handler = match self.handlers.pop() {
+            Some(h) => h,
+            None => return Err(e)
+        };
+        self.stack.truncate(handler.stack);
+        self.scope = handler.scope;
+        self.pending.truncate(handler.pending);
+        if handler.finally {
+            self.pending.push(e);
+        } else {
+            self.stack.push(e.into_value());
+        }
+        self.ip = handler.target;
+        Ok(())
+    }
+
+    // Executes one instruction, returning the frame's result once it returns
+    fn step(&mut self) -> Result<Option<LoxType>,LoxError> {
+        let op = match OpCode::from_byte(self.read_byte()) {
+            Some(op) => op,
+            None => return Err(self.internal("Invalid opcode"))
+        };
+
+        match op {
+            OpCode::Constant => {
+                let c = self.read_u16() as usize;
+                let value = match self.function.chunk.constants.get(c) {
+                    Some(v) => v.clone(),
+                    None => return Err(self.internal("Invalid constant"))
+                };
+                self.stack.push(value);
+            },
+            OpCode::Nil => self.stack.push(LoxType::Nil),
+            OpCode::True => self.stack.push(LoxType::Boolean(true)),
+            OpCode::False => self.stack.push(LoxType::Boolean(false)),
+            OpCode::Pop => { self.pop()?; },
+            OpCode::GetLocal => {
+                let depth = self.read_byte() as usize;
+                let slot = self.read_byte() as usize;
+                let value = self.ancestor(depth)?.borrow().slots.get(slot).cloned();
+                match value {
+                    Some(v) => self.stack.push(v),
+                    None => return Err(self.error("Variable not found").of_kind(ErrorKind::UndefinedVariable))
+                }
+            },
+            OpCode::SetLocal => {
+                let depth = self.read_byte() as usize;
+                let slot = self.read_byte() as usize;
+                let value = self.pop()?;
+                let scope = self.ancestor(depth)?;
+                if slot >= scope.borrow().slots.len() {
+                    return Err(self.error("Undefined variable").of_kind(ErrorKind::UndefinedVariable));
+                }
+                scope.borrow_mut().slots[slot] = value;
+                // Assignment evaluates to nil, same as the tree-walker
+                self.stack.push(LoxType::Nil);
+            },
+            OpCode::DefineLocal => {
+                let slot = self.read_byte() as usize;
+                let value = self.pop()?;
+                let scope = self.ancestor(0)?;
+                if slot >= scope.borrow().slots.len() {
+                    return Err(self.internal("Invalid local slot"));
+                }
+                scope.borrow_mut().slots[slot] = value;
+            },
+            OpCode::GetGlobal => {
+                let name = self.read_name()?;
+                let value = self.host.globals.borrow().get(&name);
+                match value {
+                    Some(v) => self.stack.push(v),
+                    None => return Err(self.error("Variable not found").of_kind(ErrorKind::UndefinedVariable))
+                }
+            },
+            OpCode::SetGlobal => {
+                let name = self.read_name()?;
+                let value = self.pop()?;
+                let defined = self.host.globals.borrow().contains(&name);
+                if !defined {
+                    return Err(self.error("Undefined variable").of_kind(ErrorKind::UndefinedVariable));
+                }
+                self.host.globals.borrow_mut().assign(&name,value);
+                self.stack.push(LoxType::Nil);
+            },
+            OpCode::DefineGlobal => {
+                let name = self.read_name()?;
+                let value = self.pop()?;
+                self.host.globals.borrow_mut().define(&name,value);
+            },
+            OpCode::GetProperty => {
+                let name = self.read_name()?;
+                let span = self.span();
+                let value = match self.pop()? {
+                    LoxType::Instance(ref instance) => LoxInstance::get(instance,&name,span)?,
+                    LoxType::Module(ref module) => module.get(&name,span)?,
+                    _ => return Err(self.error("Only instances have properties").of_kind(ErrorKind::Type))
+                };
+                self.stack.push(value);
+            },
+            OpCode::SetProperty => {
+                let name = self.read_name()?;
+                let value = self.pop()?;
+                match self.pop()? {
+                    LoxType::Instance(ref instance) => instance.borrow_mut().set(&name,value.clone()),
+                    _ => return Err(self.error("Only instances have fields").of_kind(ErrorKind::Type))
+                }
+                self.stack.push(value);
+            },
+            OpCode::List => {
+                let count = self.read_u16() as usize;
+                let start = match self.stack.len().checked_sub(count) {
+                    Some(s) => s,
+                    None => return Err(self.internal("Stack underflow"))
+                };
+                let elements = self.stack.split_off(start);
+                self.stack.push(LoxType::list(elements));
+            },
+            OpCode::Map => {
+                let count = self.read_u16() as usize;
+                let start = match self.stack.len().checked_sub(count * 2) {
+                    Some(s) => s,
+                    None => return Err(self.internal("Stack underflow"))
+                };
+                let mut map = LoxMap::new();
+                let mut entries = self.stack.split_off(start).into_iter();
+                while let (Some(key),Some(value)) = (entries.next(),entries.next()) {
+                    let key = MapKey::from_lox(&key).map_err(|e| e.located(self.span()))?;
+                    map.insert(key,value);
+                }
+                self.stack.push(LoxType::map(map));
+            },
+            OpCode::GetIndex => {
+                let index = self.pop()?;
+                let target = self.pop()?;
+                let value = get_index(target,index).map_err(|e| e.located(self.span()))?;
+                self.stack.push(value);
+            },
+            OpCode::SetIndex => {
+                let value = self.pop()?;
+                let index = self.pop()?;
+                let target = self.pop()?;
+                let value = set_index(target,index,value).map_err(|e| e.located(self.span()))?;
+                self.stack.push(value);
+            },
+            OpCode::GetSuper => {
+                let name = self.read_name()?;
+                let this = self.pop()?;
+                let method = match self.pop()? {
+                    LoxType::Class(c) => c.find_method(&name),
+                    _ => None
+                };
+                match method {
+                    Some(m) => self.stack.push(LoxType::Callable(m.bind(this))),
+                    None => return Err(self.error(&format!("Undefined property '{}'",name)).of_kind(ErrorKind::Runtime))
+                }
+            },
+            OpCode::Equal | OpCode::NotEqual | OpCode::Greater | OpCode::GreaterEqual |
+            OpCode::Less | OpCode::LessEqual | OpCode::Add | OpCode::Subtract |
+            OpCode::Multiply | OpCode::Divide => self.binary(op)?,
+            OpCode::Not => {
+                let value = bool::from(self.pop()?);
+                self.stack.push(LoxType::Boolean(!value));
+            },
+            OpCode::Negate => {
+                match self.pop()? {
+                    LoxType::Number(n) => self.stack.push(LoxType::Number(-n)),
+                    _ => return Err(self.error("Operand must be a number.").of_kind(ErrorKind::Type))
+                }
+            },
+            OpCode::Jump => {
+                let offset = self.read_u16() as usize;
+                self.ip += offset;
+            },
+            OpCode::JumpIfFalse => {
+                let offset = self.read_u16() as usize;
+                if !bool::from(self.peek(0)?.clone()) {
+                    self.ip += offset;
+                }
+            },
+            OpCode::JumpIfPassed => {
+                let param = self.read_byte() as usize;
+                let offset = self.read_u16() as usize;
+                if param < self.argc {
+                    self.ip += offset;
+                }
+            },
+            OpCode::Loop => {
+                let offset = self.read_u16() as usize;
+                self.ip -= offset;
+            },
+            OpCode::Call => {
+                let argc = self.read_byte() as usize;
+                self.call(argc)?;
+            },
+            OpCode::Closure => {
+                let index = self.read_u16() as usize;
+                let function = match self.function.chunk.functions.get(index) {
+                    Some(f) => f.clone(),
+                    None => return Err(self.internal("Invalid function"))
+                };
+                let closure = Closure::new(function,self.scope.clone());
+                self.stack.push(LoxType::Callable(Box::new(closure)));
+            },
+            OpCode::Return => return self.pop().map(Some),
+            OpCode::PushScope => {
+                let size = self.read_byte() as usize;
+                let parent = self.scope.take();
+                self.scope = Some(Scope::new(vec![LoxType::Nil; size],parent));
+            },
+            OpCode::PopScope => {
+                let parent = match self.scope {
+                    Some(ref s) => s.borrow().parent.clone(),
+                    None => None
+                };
+                self.scope = parent;
+            },
+            OpCode::Inherit => {
+                match self.peek(0)? {
+                    &LoxType::Class(_) => (),
+                    _ => return Err(self.error("Superclass must be a class").of_kind(ErrorKind::Type))
+                }
+            },
+            OpCode::Class => {
+                let name = self.read_name()?;
+                let methods = self.read_byte() as usize;
+                let has_superclass = self.read_byte() == 1;
+                self.class(name,methods,has_superclass)?;
+            },
+            OpCode::Import => {
+                let c = self.read_u16() as usize;
+                let path = match self.function.chunk.constants.get(c) {
+                    Some(v) => v.clone(),
+                    None => return Err(self.internal("Invalid constant"))
+                };
+                let module = self.import(path)?;
+                self.stack.push(LoxType::Module(module));
+            },
+            OpCode::ImportAll => {
+                if let LoxType::Module(module) = self.pop()? {
+                    for (name,value) in module.exports() {
+                        self.host.globals.borrow_mut().define(&name,value);
+                    }
+                }
+            },
+            OpCode::PushCatch | OpCode::PushFinally => {
+                let offset = self.read_u16() as usize;
+                self.handlers.push(Handler {
+                    target: self.ip + offset,
+                    stack: self.stack.len(),
+                    scope: self.scope.clone(),
+                    pending: self.pending.len(),
+                    finally: op == OpCode::PushFinally
+                });
+            },
+            OpCode::PopHandler => { self.handlers.pop(); },
+            OpCode::Throw => {
+                let value = self.pop()?;
+                return Err(LoxError::throw(value,self.span()));
+            },
+            OpCode::Rethrow => match self.pending.pop() {
+                Some(e) => return Err(e),
+                None => return Err(self.internal("Nothing to rethrow"))
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Runs a compiled script against the host's globals and natives.
+pub fn run(host: &mut Interpreter,script: Function) -> Result<(),LoxError> {
+    evaluate(host,script).map(|_| ())
+}
+
+// Runs `script` and hands back what it returns, which is nil unless it came from
+// Compiler::compile_returning()
+pub fn evaluate(host: &mut Interpreter,script: Function) -> Result<LoxType,LoxError> {
+    Frame::new(host,&script,None,0).run()
+}
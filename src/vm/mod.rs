@@ -0,0 +1,6 @@
+mod chunk;
+mod compiler;
+mod machine;
+pub use self::chunk::*;
+pub use self::compiler::*;
+pub use self::machine::*;
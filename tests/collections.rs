@@ -0,0 +1,110 @@
+extern crate lax;
+
+mod common;
+
+use lax::{Lax,LoxType};
+use common::both;
+
+fn eval(lax: &mut Lax,source: &str) -> String {
+    match lax.eval(source) {
+        Ok(v) => format!("{}",v),
+        Err(e) => e.message().to_string()
+    }
+}
+
+#[test]
+fn list_natives() {
+    for mut lax in both() {
+        lax.eval("var xs = [3, 1, 2];").unwrap();
+        assert_eq!(eval(&mut lax,"len(xs)"),"3");
+        assert_eq!(eval(&mut lax,"len(\"héllo\")"),"5");
+
+        lax.eval("push(xs, 5, 4);").unwrap();
+        assert_eq!(eval(&mut lax,"xs"),"[3, 1, 2, 5, 4]");
+        assert_eq!(eval(&mut lax,"pop(xs)"),"4");
+
+        lax.eval("insert(xs, 0, 0); insert(xs, len(xs), 9);").unwrap();
+        assert_eq!(eval(&mut lax,"xs"),"[0, 3, 1, 2, 5, 9]");
+        assert_eq!(eval(&mut lax,"remove(xs, 1)"),"3");
+        assert_eq!(eval(&mut lax,"remove(xs, 0 - 1)"),"9");
+
+        assert_eq!(eval(&mut lax,"slice(xs, 1)"),"[1, 2, 5]");
+        assert_eq!(eval(&mut lax,"slice(xs, 1, 0 - 1)"),"[1, 2]");
+        assert_eq!(eval(&mut lax,"slice(xs, 10)"),"[]");
+
+        lax.eval("sort(xs);").unwrap();
+        assert_eq!(eval(&mut lax,"xs"),"[0, 1, 2, 5]");
+        let words = lax.eval("var w = [\"b\", \"c\", \"a\"]; sort(w); w").unwrap();
+        assert_eq!(format!("{}",words),"[\"a\", \"b\", \"c\"]");
+    }
+}
+
+#[test]
+fn list_errors() {
+    for mut lax in both() {
+        lax.eval("var xs = [1, 2];").unwrap();
+        assert_eq!(eval(&mut lax,"xs[2]"),"Index 2 is out of range for a list of length 2");
+        assert_eq!(eval(&mut lax,"xs[0 - 3]"),"Index -3 is out of range for a list of length 2");
+        assert_eq!(eval(&mut lax,"xs[0.5]"),"List index must be a whole number but got 0.5");
+        assert_eq!(eval(&mut lax,"xs[\"0\"]"),"List index must be a number but got string");
+        assert_eq!(eval(&mut lax,"1[0]"),"Can't index a number");
+        assert_eq!(eval(&mut lax,"pop([])"),"Can't pop from an empty list");
+        assert_eq!(eval(&mut lax,"insert(xs, 3, 0)"),"Index 3 is out of range for a list of length 2");
+        assert_eq!(eval(&mut lax,"sort([1, \"a\"])"),"Can only sort a list of numbers or a list of strings");
+        assert_eq!(eval(&mut lax,"push(1, 2)"),"Argument 1 of 'push' must be a list but got number");
+
+        let e = lax.eval("\nxs[5] = 1;").unwrap_err();
+        assert_eq!((e.line(),e.span().map(|s| s.column)),(2,Some(3)));
+    }
+}
+
+#[test]
+fn lists_cross_the_boundary() {
+    for mut lax in both() {
+        lax.set_global("xs",LoxType::list(vec![LoxType::Number(1.0)]));
+        lax.eval("push(xs, 2);").unwrap();
+        match lax.get_global("xs") {
+            Some(LoxType::List(l)) => assert_eq!(l.borrow().len(),2),
+            v => panic!("expected a list, got {:?}",v)
+        }
+    }
+}
+
+#[test]
+fn map_natives() {
+    for mut lax in both() {
+        lax.eval("var m = {\"a\": 1, 2: \"two\", true: nil};").unwrap();
+        assert_eq!(eval(&mut lax,"len(m)"),"3");
+        assert_eq!(eval(&mut lax,"keys(m)"),"[\"a\", 2, true]");
+        assert_eq!(eval(&mut lax,"values(m)"),"[1, \"two\", nil]");
+        assert_eq!(eval(&mut lax,"has(m, 2)"),"true");
+        assert_eq!(eval(&mut lax,"has(m, \"2\")"),"false");
+
+        assert_eq!(eval(&mut lax,"delete(m, \"a\")"),"1");
+        assert_eq!(eval(&mut lax,"delete(m, \"a\")"),"nil");
+        lax.eval("m[\"a\"] = 3;").unwrap();
+        assert_eq!(eval(&mut lax,"m"),"{2: \"two\", true: nil, \"a\": 3}");
+    }
+}
+
+#[test]
+fn maps_display_as_literals() {
+    for mut lax in both() {
+        let printed = eval(&mut lax,"{\"a \\\"key\\\"\\n\": 1, 2.5: [\"x\", \"$1\"], false: {}, \"\\${x}\": nil}");
+        assert_eq!(printed,"{\"a \\\"key\\\"\\n\": 1, 2.5: [\"x\", \"\\$1\"], false: {}, \"\\${x}\": nil}");
+        assert_eq!(eval(&mut lax,&printed),printed);
+    }
+}
+
+#[test]
+fn map_errors() {
+    for mut lax in both() {
+        lax.eval("var m = {\"a\": 1};").unwrap();
+        assert_eq!(eval(&mut lax,"m[\"b\"]"),"No key \"b\" in map");
+        assert_eq!(eval(&mut lax,"m[nil]"),"Map keys must be strings, numbers or booleans but got nil");
+        assert_eq!(eval(&mut lax,"{[]: 1}"),"Map keys must be strings, numbers or booleans but got list");
+        assert_eq!(eval(&mut lax,"m[0/0] = 1"),"A map key can't be NaN");
+        assert_eq!(eval(&mut lax,"has(m, [])"),"Map keys must be strings, numbers or booleans but got list");
+        assert_eq!(eval(&mut lax,"keys([])"),"Argument 1 of 'keys' must be a map but got list");
+    }
+}
@@ -0,0 +1,86 @@
+// Helpers shared by the integration tests. Each test file is its own crate and uses only some
+// of them, hence the allow.
+#![allow(dead_code)]
+
+use lax::{Lax,LoxError};
+use lax::scanner::Scanner;
+use lax::parser::Parser;
+use lax::resolver::Resolver;
+use lax::interpreter::Interpreter;
+use lax::vm;
+use std::cell::RefCell;
+use std::io::{self,Write};
+use std::path::Path;
+use std::rc::Rc;
+
+// The same embedding on the tree-walker and on the bytecode VM
+pub fn both() -> Vec<Lax> {
+    vec![Lax::new(),Lax::new().vm(true)]
+}
+
+// Output the test reads back once the interpreter has written to its copy
+#[derive(Clone)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    pub fn new() -> Capture {
+        Capture(Rc::new(RefCell::new(Vec::new())))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.text().lines().map(String::from).collect()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self,buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug,Clone,Copy)]
+pub enum Backend {
+    TreeWalker,
+    Vm
+}
+
+// Runs a script file through the whole pipeline on one backend, returning the lines it printed
+// and the error that stopped it. Errors before it runs come back as their text.
+pub fn run(path: &Path,source: &str,backend: Backend) -> Result<(Vec<String>,Option<LoxError>),String> {
+    let mut scanner = Scanner::new(source.to_string());
+    if let Err(e) = scanner.scan() {
+        return Err(format!("scan error: {:?}",e));
+    }
+    let mut parser = Parser::new(scanner.tokens);
+    let ast = match parser.parse() {
+        Ok(ast) => ast,
+        Err(errs) => return Err(errs.iter().map(|e| format!("{}",e)).collect::<Vec<String>>().join(""))
+    };
+    if let Err(errs) = Resolver::new().resolve(&ast) {
+        return Err(errs.iter().map(|e| format!("{}",e)).collect::<Vec<String>>().join(""));
+    }
+
+    let output = Capture::new();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_file(path);
+    interpreter.set_output(output.clone());
+    lax::lox_std::install(&mut interpreter.builtins.borrow_mut());
+
+    let result = match backend {
+        Backend::TreeWalker => interpreter.interpret(&ast),
+        Backend::Vm => match vm::Compiler::new().compile(&ast) {
+            Ok(script) => vm::run(&mut interpreter,script),
+            Err(e) => return Err(format!("compile error: {}",e))
+        }
+    };
+
+    Ok((output.lines(),result.err()))
+}
@@ -0,0 +1,55 @@
+extern crate lax;
+
+mod common;
+
+use common::{Backend,run};
+use std::fs::{self,File};
+use std::io::Read;
+use std::path::{Path,PathBuf};
+
+// Everything a run printed, then the error that stopped it, if any. A sample that doesn't get
+// as far as running compares equal on both backends and proves nothing, so that's an error.
+fn output(path: &Path,source: &str,backend: Backend) -> Result<Vec<String>,String> {
+    let (mut lines,error) = run(path,source,backend)?;
+    lines.extend(error.map(|e| format!("{}",e)));
+    Ok(lines)
+}
+
+fn samples() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("samples");
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e == "lox").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn backends_agree_on_every_sample() {
+    let files = samples();
+    assert!(!files.is_empty(),"no .lox samples found");
+
+    let mut failures = Vec::new();
+    for path in &files {
+        let mut source = String::new();
+        File::open(path).unwrap().read_to_string(&mut source).unwrap();
+
+        let expected = match output(path,&source,Backend::TreeWalker) {
+            Ok(lines) => lines,
+            Err(e) => {
+                failures.push(format!("{}: doesn't run: {}",path.display(),e));
+                continue;
+            }
+        };
+        let actual = output(path,&source,Backend::Vm).unwrap_or_else(|e| vec![e]);
+        if expected != actual {
+            failures.push(format!("{}:\n  tree-walker: {:?}\n  vm:          {:?}",path.display(),expected,actual));
+        }
+    }
+
+    assert!(failures.is_empty(),"backends disagree:\n{}",failures.join("\n"));
+}
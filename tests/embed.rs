@@ -0,0 +1,353 @@
+extern crate lax;
+
+mod common;
+
+use lax::{Lax,LoxType,ErrorKind,Span,MAX_DEPTH,MAX_NESTING};
+use lax::interpreter::Interpreter;
+use lax::vm::{self,Chunk,Function,OpCode};
+use common::{both,run,Backend,Capture};
+use std::io::{self,Cursor,Read,Write};
+use std::fs::{self,File};
+use std::path::Path;
+
+#[test]
+fn eval_returns_the_last_expression() {
+    for mut lax in both() {
+        assert_eq!(lax.eval("1 + 2").unwrap(),LoxType::Number(3.0));
+        assert_eq!(lax.eval("var a = \"lo\"; \"hel\" + a;").unwrap(),LoxType::String("hello".to_string()));
+        assert_eq!(lax.eval("var b = 1;").unwrap(),LoxType::Nil);
+        assert_eq!(lax.eval("").unwrap(),LoxType::Nil);
+    }
+}
+
+#[test]
+fn globals_cross_the_boundary() {
+    for mut lax in both() {
+        lax.set_global("limit",LoxType::Number(10.0));
+        lax.eval("var doubled = limit * 2;").unwrap();
+        assert_eq!(lax.get_global("doubled"),Some(LoxType::Number(20.0)));
+        assert_eq!(lax.get_global("missing"),None);
+    }
+}
+
+#[test]
+fn host_calls_lox_functions() {
+    for mut lax in both() {
+        lax.eval("fun add(a, b) { return a + b; } class Point { init(x) { this.x = x; } }").unwrap();
+        let sum = lax.call_function("add",vec![LoxType::Number(2.0),LoxType::Number(3.0)]).unwrap();
+        assert_eq!(sum,LoxType::Number(5.0));
+
+        match lax.call_function("Point",vec![LoxType::Number(1.0)]).unwrap() {
+            LoxType::Instance(_) => (),
+            v => panic!("expected an instance, got {:?}",v)
+        }
+
+        assert!(lax.call_function("add",vec![LoxType::Number(2.0)]).is_err());
+        assert!(lax.call_function("nope",vec![]).is_err());
+        lax.eval("var n = 1;").unwrap();
+        assert!(lax.call_function("n",vec![]).is_err());
+
+        lax.eval("var twice = (x) => x * 2; var greet = fun (name) { return \"hi \" + name; };").unwrap();
+        assert_eq!(lax.call_function("twice",vec![LoxType::Number(4.0)]).unwrap(),LoxType::Number(8.0));
+        assert_eq!(lax.call_function("greet",vec![LoxType::String("bob".to_string())]).unwrap(),LoxType::String("hi bob".to_string()));
+    }
+}
+
+#[test]
+fn run_file_reports_the_file() {
+    let path = std::env::temp_dir().join("lax_embed_run_file.lox");
+    File::create(&path).unwrap().write_all(b"var ran = true;\nran + 1;\n").unwrap();
+
+    for mut lax in both() {
+        let e = lax.run_file(&path).unwrap_err();
+        assert_eq!(e.line(),2);
+        assert_eq!(e.file(),Some(path.to_string_lossy().as_ref()));
+        assert_eq!(lax.get_global("ran"),Some(LoxType::Boolean(true)));
+        assert_eq!(lax.run_file("does/not/exist.lox").unwrap_err().kind(),ErrorKind::Io);
+    }
+    fs::remove_file(&path).ok();
+}
+
+// None of these may take the host down, only come back as errors
+#[test]
+fn hostile_scripts_are_errors() {
+    let deep_parens = format!("{}1{};","(".repeat(5000),")".repeat(5000));
+    let deep_blocks = format!("{}{}","{".repeat(5000),"}".repeat(5000));
+    let long_chain = format!("nil{};",".x".repeat(5000));
+    let scripts = vec![
+        "fun f() { return f(); } f();",
+        "fun f(n) { return f(n + 1) + 1; } f(0);",
+        "class A { init() { A(); } } A();",
+        "import 12;",
+        "import \"does/not/exist.lox\";",
+        "var x = ;",
+        "\"unterminated",
+        "1 +",
+        "this;",
+        "return 1;",
+        "nil();",
+        "1.foo = 2;",
+        "print();",
+        "max();",
+        "clock(1);",
+        &deep_parens,
+        &deep_blocks,
+        &long_chain
+    ];
+
+    for script in scripts {
+        for mut lax in both() {
+            assert!(lax.eval(script).is_err(),"{:.40} should fail",script);
+        }
+    }
+
+    // Operator chains are walked in a loop, so any length runs
+    let long_sum = format!("1{};","+1".repeat(5000));
+    for mut lax in both() {
+        assert_eq!(lax.eval(&long_sum).unwrap(),LoxType::Number(5001.0));
+        assert_eq!(lax.eval(&format!("nil{} or 1;"," and 1".repeat(5000))).unwrap(),LoxType::Number(1.0));
+    }
+}
+
+#[test]
+fn string_literal_errors() {
+    let cases = vec![
+        ("\"\\q\";","Unknown escape sequence '\\q'",2),
+        ("\"ok\\u{110000}\";","\\u{110000} isn't a valid character",4),
+        ("\"\\u{}\";","Expected \\u{...} with 1 to 6 hex digits",2),
+        ("\"${1 + 2\";","Unterminated string",9),
+        ("\"a ${1} b","Unterminated string",7)
+    ];
+
+    for (source,message,column) in cases {
+        let e = Lax::new().eval(source).unwrap_err();
+        assert_eq!(e.message(),message,"{}",source);
+        assert_eq!(e.span().map(|s| s.column),Some(column),"{}",source);
+    }
+}
+
+// The limits count calls and nesting in source, so they're the same on both backends and in
+// every build, whatever thread the host calls from
+#[test]
+fn limits_dont_depend_on_the_backend() {
+    let count = "fun count(n) { if (n > 0) return count(n - 1) + 1; return 0; }";
+    let nested = |n: usize| format!("{}1{};","(".repeat(n),")".repeat(n));
+    for mut lax in both() {
+        lax.eval(count).unwrap();
+        assert_eq!(lax.eval(&format!("count({})",MAX_DEPTH - 1)).unwrap(),LoxType::Number((MAX_DEPTH - 1) as f64));
+        assert_eq!(lax.eval(&format!("count({})",MAX_DEPTH)).unwrap_err().message(),"Stack overflow");
+
+        assert_eq!(lax.eval(&nested(MAX_NESTING - 2)).unwrap(),LoxType::Number(1.0));
+        assert_eq!(lax.eval(&nested(MAX_NESTING)).unwrap_err().message(),"Too much nesting");
+    }
+}
+
+#[test]
+fn recursion_reports_a_backtrace() {
+    for mut lax in both() {
+        let e = lax.eval("fun down(n) {\n  if (n == 0) return nil + 1;\n  return down(n - 1);\n}\ndown(3);").unwrap_err();
+        let frames = e.backtrace();
+        assert_eq!(frames.len(),5);
+        assert_eq!((frames[0].function.as_str(),frames[0].line),("down",2));
+        assert_eq!((frames[1].function.as_str(),frames[1].line),("down",3));
+        assert_eq!((frames[4].function.as_str(),frames[4].line),("<script>",5));
+    }
+}
+
+#[test]
+fn backtrace_sample_renders_every_frame() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("samples").join("backtrace.lox");
+    let mut source = String::new();
+    File::open(&path).unwrap().read_to_string(&mut source).unwrap();
+
+    for &backend in &[Backend::TreeWalker,Backend::Vm] {
+        let (_,error) = run(&path,&source,backend).unwrap();
+        let rendered = error.expect("backtrace.lox should fail").render(&source);
+        assert_eq!(rendered,concat!(
+            "error[E004]: Can't add two different types\n",
+            " --> <input>:2:23\n",
+            "  |\n",
+            "2 |   if (n < 2) return n + nil;\n",
+            "  |                       ^\n",
+            "    at fib (line 2)\n",
+            "    at fib (line 3)\n",
+            "    at fib (line 3)\n",
+            "    at main (line 7)\n",
+            "    at <script> (line 10)\n"
+        ),"{:?}",backend);
+    }
+}
+
+#[test]
+fn break_and_continue_stay_inside_loops() {
+    for source in &["break;","if (true) continue;","while (true) { fun f() { break; } }","class A { m() { continue; } }"] {
+        let e = Lax::new().eval(source).unwrap_err();
+        assert!(e.message().starts_with("Can't use"),"{}: {}",source,e.message());
+    }
+
+    for mut lax in both() {
+        let v = lax.eval("var s = 0; for (var i = 0; i < 100; i = i + 1) { if (i == 3) continue; if (i > 6) break; s = s + i; } s").unwrap();
+        assert_eq!(v,LoxType::Number(18.0));
+    }
+}
+
+#[test]
+fn errors_say_what_kind_they_are() {
+    let cases = vec![
+        ("\"abc",ErrorKind::Lexical),
+        ("var = 1;",ErrorKind::Syntax),
+        ("{ var a = a; }",ErrorKind::Resolution),
+        ("1 - \"x\";",ErrorKind::Type),
+        ("nil + 1;",ErrorKind::Type),
+        ("len(1);",ErrorKind::Type),
+        ("max(\"a\");",ErrorKind::Type),
+        ("1();",ErrorKind::Type),
+        ("fun f(a) {} f();",ErrorKind::Arity),
+        ("clock(1);",ErrorKind::Arity),
+        ("nowhere;",ErrorKind::UndefinedVariable),
+        ("nowhere = 1;",ErrorKind::UndefinedVariable),
+        ("[1][5];",ErrorKind::Runtime),
+        ("import \"nowhere\";",ErrorKind::Import)
+    ];
+
+    for mut lax in both() {
+        for &(source,kind) in &cases {
+            let e = lax.eval(source).unwrap_err();
+            assert_eq!(e.kind(),kind,"{}",source);
+        }
+        assert_eq!(lax.call_function("nowhere",vec![]).unwrap_err().kind(),ErrorKind::UndefinedVariable);
+    }
+
+    let e = Lax::new().eval("1 - nil;").unwrap_err();
+    assert_eq!(e.code(),"E004");
+    assert!(e.render("1 - nil;").starts_with("error[E004]: "));
+}
+
+#[test]
+fn render_points_at_the_whole_token() {
+    let source = "var total = 0;\n\tprint(total + missing_value);";
+    for mut lax in both() {
+        let e = lax.eval(source).unwrap_err();
+        assert_eq!(e.render(source),concat!(
+            "error[E006]: Variable not found\n",
+            " --> <input>:2:16\n",
+            "  |\n",
+            "2 |     print(total + missing_value);\n",
+            "  |                   ^^^^^^^^^^^^^\n"
+        ));
+    }
+}
+
+#[test]
+fn runtime_errors_are_caught_as_values() {
+    for mut lax in both() {
+        let caught = "var caught; try { %s } catch (e) { caught = e; }";
+        for &(source,code) in &[("nil + 1;","E004"),("fun f(a) {} f();","E005"),("nowhere;","E006"),("[1][5];","E009")] {
+            lax.eval(&caught.replace("%s",source)).unwrap();
+            assert_eq!(lax.eval("caught.code").unwrap(),LoxType::String(code.to_string()),"{}",source);
+            assert_eq!(lax.eval("caught.line").unwrap(),LoxType::Number(1.0));
+        }
+        assert_eq!(lax.eval("caught.message").unwrap(),LoxType::String("Index 5 is out of range for a list of length 1".to_string()));
+
+        lax.eval("var log = \"\"; try { try { throw 42; } finally { log = log + \"f\"; } } catch (e) { log = log + e; }").unwrap();
+        assert_eq!(lax.get_global("log"),Some(LoxType::String("f42".to_string())));
+    }
+}
+
+#[test]
+fn broken_bytecode_isnt_caught() {
+    // try { <invalid opcode> } catch (e) { return nil; }
+    let mut chunk = Chunk::new();
+    for &byte in &[OpCode::PushCatch as u8,0,1,0xff,OpCode::Nil as u8,OpCode::Return as u8] {
+        chunk.write(byte,Span::default());
+    }
+    let script = Function { name: "<script>".to_string(), arity: 0, required: 0, locals: 0, initializer: false, chunk: chunk };
+
+    let e = vm::run(&mut Interpreter::new(),script).unwrap_err();
+    assert_eq!(e.message(),"Invalid opcode");
+    assert_eq!(e.kind(),ErrorKind::Internal);
+}
+
+#[test]
+fn missing_properties_are_runtime_errors() {
+    for mut lax in both() {
+        lax.eval("class A {} class B < A { m() { return super.missing; } }").unwrap();
+        for source in &["A().missing;","B().m();"] {
+            let e = lax.eval(source).unwrap_err();
+            assert_eq!((e.kind(),e.code()),(ErrorKind::Runtime,"E009"),"{}",source);
+            assert_eq!(e.message(),"Undefined property 'missing'","{}",source);
+        }
+    }
+}
+
+// The messages are pinned by the operator files under tests/lox
+#[test]
+fn operators_on_the_wrong_types_are_type_errors() {
+    for mut lax in both() {
+        for source in &["-\"s\";","-nil;","\"1\" - 1;","1 * nil;","true + 1;","1 < \"a\";","nil >= false;","[] > []"] {
+            assert_eq!(lax.eval(source).unwrap_err().kind(),ErrorKind::Type,"{}",source);
+        }
+    }
+}
+
+#[test]
+fn uncaught_throws_keep_the_value() {
+    for mut lax in both() {
+        let e = lax.eval("fun fail() { throw 7; }\nfail();").unwrap_err();
+        assert_eq!(e.kind(),ErrorKind::Thrown);
+        assert_eq!(e.code(),"E010");
+        assert_eq!(e.thrown(),Some(&LoxType::Number(7.0)));
+        assert_eq!((e.message(),e.line()),("7",1));
+
+        // Rethrowing a caught error reads like the original
+        let e = lax.eval("try { nil + 1; } catch (e) { throw e; }").unwrap_err();
+        assert_eq!(e.message(),"Can't add two different types");
+        // finally without a catch passes the original error on
+        let e = lax.eval("try { nil + 1; } finally {}").unwrap_err();
+        assert_eq!(e.kind(),ErrorKind::Type);
+    }
+}
+
+#[test]
+fn jumps_cant_leave_a_finally_block() {
+    for source in &["fun f() { try {} finally { return 1; } }","while (true) { try {} finally { break; } }","try {}"] {
+        assert_eq!(Lax::new().eval(source).unwrap_err().kind(),ErrorKind::Syntax,"{}",source);
+    }
+    let v = Lax::new().eval("var n = 0; try {} finally { while (true) { n = n + 1; if (n == 3) break; } } n").unwrap();
+    assert_eq!(v,LoxType::Number(3.0));
+}
+
+#[test]
+fn output_and_input_can_be_redirected() {
+    for use_vm in vec![false,true] {
+        let out = Capture::new();
+        let mut lax = Lax::new().vm(use_vm).output(out.clone()).input(Cursor::new("ann\nbob\n"));
+        lax.eval("var first = readline(); print(\"hi\", first + readline()); print(readline() == \"\");").unwrap();
+        assert_eq!(out.text(),"hi ann\nbob\n\ntrue\n");
+
+        // Swapped while running, the next print goes to the new place
+        let later = Capture::new();
+        lax.interpreter().set_output(later.clone());
+        lax.eval("print(1);").unwrap();
+        assert_eq!(later.text(),"1\n");
+    }
+}
+
+#[test]
+fn failed_writes_are_io_errors() {
+    struct Closed;
+    impl Write for Closed {
+        fn write(&mut self,_: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe,"closed"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    for mut lax in vec![Lax::new().output(Closed),Lax::new().vm(true).output(Closed)] {
+        let e = lax.eval("print(1);").unwrap_err();
+        assert_eq!(e.kind(),ErrorKind::Io);
+        assert_eq!(e.line(),1);
+    }
+}
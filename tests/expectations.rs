@@ -0,0 +1,112 @@
+extern crate lax;
+
+mod common;
+
+use common::{Backend,run};
+use std::fs::{self,File};
+use std::io::Read;
+use std::path::{Path,PathBuf};
+
+// Runs every .lox file under tests/lox on both backends and checks it against the comments in
+// it, the same way the reference Lox test suite does:
+//
+//     print(1 + 2); // expect: 3
+//     nil + 1;      // expect runtime error: Can't add two different types
+//
+// Each `expect:` is one line printed, in order. An `expect runtime error:` is the message of the
+// error that stops the script, raised on the line the comment is on.
+
+#[derive(Debug,PartialEq)]
+struct RuntimeError {
+    line: i32,
+    message: String
+}
+
+struct Expectations {
+    output: Vec<String>,
+    error: Option<RuntimeError>
+}
+
+const EXPECT: &'static str = "// expect: ";
+const EXPECT_ERROR: &'static str = "// expect runtime error: ";
+
+fn expectations(source: &str) -> Expectations {
+    let mut expected = Expectations { output: Vec::new(), error: None };
+    for (n,line) in source.lines().enumerate() {
+        if let Some(i) = line.find(EXPECT) {
+            expected.output.push(line[i + EXPECT.len()..].to_string());
+        } else if let Some(i) = line.find(EXPECT_ERROR) {
+            expected.error = Some(RuntimeError { line: n as i32 + 1, message: line[i + EXPECT_ERROR.len()..].to_string() });
+        }
+    }
+    expected
+}
+
+// Line by line, so a long file that goes wrong halfway says where
+fn diff(expected: &[String],actual: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i),actual.get(i)) {
+            (Some(e),Some(a)) if e == a => (),
+            (Some(e),Some(a)) => out.push(format!("output {}: expected {:?}, got {:?}",i + 1,e,a)),
+            (Some(e),None) => out.push(format!("output {}: expected {:?}, got nothing",i + 1,e)),
+            (None,Some(a)) => out.push(format!("output {}: unexpected {:?}",i + 1,a)),
+            (None,None) => ()
+        }
+    }
+    out
+}
+
+fn check(path: &Path,backend: Backend) -> Vec<String> {
+    let mut source = String::new();
+    File::open(path).unwrap().read_to_string(&mut source).unwrap();
+    let expected = expectations(&source);
+
+    let (output,error) = match run(path,&source,backend) {
+        Ok((output,error)) => (output,error.map(|e| RuntimeError { line: e.line(), message: e.message().to_string() })),
+        Err(e) => return vec![e]
+    };
+
+    let mut problems = diff(&expected.output,&output);
+    match (&expected.error,&error) {
+        (&Some(ref e),&Some(ref a)) if e == a => (),
+        (&Some(ref e),&Some(ref a)) => problems.push(format!("expected runtime error {:?} in line {}, got {:?} in line {}",e.message,e.line,a.message,a.line)),
+        (&Some(ref e),&None) => problems.push(format!("expected runtime error {:?} in line {}, got none",e.message,e.line)),
+        (&None,&Some(ref a)) => problems.push(format!("unexpected runtime error {:?} in line {}",a.message,a.line)),
+        (&None,&None) => ()
+    }
+    problems
+}
+
+fn lox_files(dir: &Path,files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            lox_files(&path,files);
+        } else if path.extension().map(|e| e == "lox").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn lox_files_meet_their_expectations() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("lox");
+    let mut files = Vec::new();
+    lox_files(&root,&mut files);
+    files.sort();
+    assert!(!files.is_empty(),"no .lox files under {}",root.display());
+
+    let mut failures = Vec::new();
+    for path in &files {
+        for &backend in &[Backend::TreeWalker,Backend::Vm] {
+            let problems = check(path,backend);
+            if !problems.is_empty() {
+                let name = path.strip_prefix(&root).unwrap_or(path).display();
+                failures.push(format!("{} ({:?}):\n  {}",name,backend,problems.join("\n  ")));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(),"{} of {} runs failed:\n{}",failures.len(),files.len() * 2,failures.join("\n"));
+}
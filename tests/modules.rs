@@ -0,0 +1,166 @@
+extern crate lax;
+
+mod common;
+
+use lax::{Lax,LoxType,LoxError,ImportError};
+use common::both;
+use std::path::{Path,PathBuf};
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn modules() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("samples").join("modules")
+}
+
+fn error(lax: &mut Lax,source: &str) -> String {
+    lax.eval(source).unwrap_err().message().to_string()
+}
+
+// Follows modules that failed because something they imported did
+fn innermost(e: &LoxError) -> &LoxError {
+    match e.import_error() {
+        Some(&ImportError::Runtime(_,_,ref inner)) => innermost(inner),
+        _ => e
+    }
+}
+
+#[test]
+fn modules_run_once_however_they_are_spelled() {
+    for mut lax in both() {
+        let loads = Rc::new(Cell::new(0.0));
+        let counter = loads.clone();
+        lax.register("loaded",move || { counter.set(counter.get() + 1.0); counter.get() });
+        lax.interpreter().set_search_path(vec![modules()]);
+        lax.eval("import \"counter\" as a; import \"counter.lox\" as b; from \"./counter\" import count;").unwrap();
+        lax.eval("import \"counter\";").unwrap();
+
+        assert_eq!(loads.get(),1.0);
+        assert_eq!(lax.eval("a == b").unwrap(),LoxType::Boolean(true));
+        assert_eq!(lax.eval("count").unwrap(),LoxType::Number(1.0));
+    }
+}
+
+#[test]
+fn namespaces_keep_module_globals_out_of_the_importer() {
+    for mut lax in both() {
+        lax.interpreter().set_search_path(vec![modules()]);
+        lax.eval("import \"geometry\" as geo;").unwrap();
+
+        assert_eq!(lax.get_global("scale"),None);
+        assert_eq!(lax.eval("geo.scale(2)").unwrap(),LoxType::Number(4.0));
+        // Functions keep seeing their module's globals, not the caller's
+        assert_eq!(lax.eval("var unit = 100; geo.Square(2).area()").unwrap(),LoxType::Number(4.0));
+    }
+}
+
+#[test]
+fn modules_only_share_natives_with_the_importer() {
+    for mut lax in both() {
+        lax.interpreter().set_search_path(vec![modules()]);
+        lax.set_global("secret",LoxType::Number(1.0));
+        let e = lax.eval("import \"peek\";").unwrap_err();
+        assert_eq!(innermost(&e).message(),"Variable not found");
+        assert_eq!(lax.get_global("secret"),Some(LoxType::Number(1.0)));
+
+        // A glob import binds globals, even from inside a block
+        lax.eval("{ import \"geometry\"; }").unwrap();
+        assert_eq!(lax.eval("unit").unwrap(),LoxType::Number(1.0));
+    }
+}
+
+#[test]
+fn imports_are_found_next_to_the_importing_file_first() {
+    for mut lax in both() {
+        lax.interpreter().set_search_path(Vec::new());
+        assert_eq!(error(&mut lax,"import \"geometry\";"),"Couldn't find module 'geometry'");
+
+        lax.interpreter().set_search_path(vec![modules().join("elsewhere")]);
+        lax.eval("import \"geometry\" as far;").unwrap();
+        assert_eq!(lax.eval("far.unit").unwrap(),LoxType::Number(2.0));
+
+        // Inside a module, its own directory comes before the search path
+        lax.interpreter().set_file(modules().join("main.lox"));
+        lax.eval("import \"geometry\" as geo;").unwrap();
+        assert_eq!(lax.eval("geo.unit").unwrap(),LoxType::Number(1.0));
+    }
+}
+
+#[test]
+fn import_errors() {
+    for mut lax in both() {
+        lax.interpreter().set_search_path(vec![modules()]);
+
+        let e = lax.eval("import \"cycle_a\";").unwrap_err();
+        let cycle = innermost(&e);
+        match cycle.import_error() {
+            Some(&ImportError::Cycle(ref modules)) => {
+                assert_eq!(modules.len(),3);
+                assert!(modules[0].ends_with("cycle_a.lox") && modules[1].ends_with("cycle_b.lox"));
+                assert_eq!(modules[0],modules[2]);
+            },
+            other => panic!("expected a cycle, got {:?}",other)
+        }
+        assert!(cycle.message().starts_with("Import cycle: "),"{}",cycle.message());
+        assert!(cycle.file().unwrap().ends_with("cycle_b.lox"));
+
+        assert_eq!(error(&mut lax,"from \"geometry\" import scale, rotate;"),"Module 'geometry' has no 'rotate'");
+        assert_eq!(error(&mut lax,"import \"geometry\" as geo; geo.rotate;"),"Module 'geometry' has no 'rotate'");
+        assert_eq!(lax.get_global("scale"),None);
+    }
+}
+
+#[test]
+fn import_failures_have_distinct_kinds() {
+    for mut lax in both() {
+        lax.interpreter().set_search_path(vec![modules()]);
+
+        match lax.eval("import \"nowhere\";").unwrap_err().import_error() {
+            Some(&ImportError::NotFound) => (),
+            other => panic!("expected not found, got {:?}",other)
+        }
+
+        let e = lax.eval("import \"syntax_error\";").unwrap_err();
+        assert_eq!(e.message(),"Module 'syntax_error' has errors");
+        match e.import_error() {
+            Some(&ImportError::Parse(ref path,_,ref errs)) => {
+                assert!(path.ends_with("syntax_error.lox"));
+                assert_eq!(errs.iter().map(|e| e.line()).collect::<Vec<i32>>(),vec![1,2]);
+                assert!(errs.iter().all(|e| e.file() == Some(&path.display().to_string()[..])));
+            },
+            other => panic!("expected a parse error, got {:?}",other)
+        }
+
+        // Reported at the import, with the module's own error inside
+        let e = lax.eval("\n  import \"broken\" as b;").unwrap_err();
+        assert_eq!((e.line(),e.span().unwrap().column),(2,10));
+        match e.import_error() {
+            Some(&ImportError::Runtime(ref path,ref source,ref inner)) => {
+                assert!(path.ends_with("broken.lox"));
+                assert!(source.starts_with("var fine = 1;"));
+                assert_eq!(inner.line(),4);
+                assert_eq!(inner.file(),Some(&path.display().to_string()[..]));
+                assert_eq!(inner.backtrace().len(),2);
+                assert!(e.render("\n  import \"broken\" as b;").contains("4 |     return nil + 1;"));
+            },
+            other => panic!("expected a runtime error, got {:?}",other)
+        }
+
+        // A module that failed isn't cached, and binds nothing
+        assert_eq!(lax.get_global("b"),None);
+        assert_eq!(lax.get_global("fine"),None);
+        assert_eq!(error(&mut lax,"import \"broken\";"),"Module 'broken' failed while running");
+    }
+}
+
+#[test]
+fn errors_raised_in_module_functions_point_into_the_module() {
+    for mut lax in both() {
+        lax.interpreter().set_search_path(vec![modules()]);
+        lax.eval("import \"late_error\" as m;").unwrap();
+
+        let e = lax.eval("m.later()").unwrap_err();
+        assert_eq!(e.message(),"Variable not found");
+        assert!(e.file().unwrap().ends_with("late_error.lox"));
+        assert_eq!(e.line(),2);
+    }
+}
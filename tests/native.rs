@@ -0,0 +1,91 @@
+extern crate lax;
+
+mod common;
+
+use lax::{Lax,LoxError,LoxType,Rest};
+use common::both;
+
+fn hypot(a: f64,b: f64) -> f64 {
+    (a * a + b * b).sqrt()
+}
+
+#[test]
+fn typed_parameters_and_returns() {
+    for mut lax in both() {
+        lax.register("hypot",hypot);
+        lax.register("shout",|s: String| s.to_uppercase());
+        lax.register("both",|a: bool,b: bool| a && b);
+        lax.register("kind",|v: LoxType| v.type_name().to_string());
+        lax.register("answer",|| 42.0);
+
+        assert_eq!(lax.eval("hypot(3, 4)").unwrap(),LoxType::Number(5.0));
+        assert_eq!(lax.eval("shout(\"hi\")").unwrap(),LoxType::String("HI".to_string()));
+        assert_eq!(lax.eval("both(true, false)").unwrap(),LoxType::Boolean(false));
+        assert_eq!(lax.eval("kind(nil)").unwrap(),LoxType::String("nil".to_string()));
+        assert_eq!(lax.eval("answer()").unwrap(),LoxType::Number(42.0));
+    }
+}
+
+#[test]
+fn optional_parameters_take_nil() {
+    for mut lax in both() {
+        lax.register("greet",|name: Option<String>| format!("hello {}",name.unwrap_or("you".to_string())));
+        lax.register("half",|n: f64| if n % 2.0 == 0.0 { Some(n / 2.0) } else { None });
+
+        assert_eq!(lax.eval("greet(nil)").unwrap(),LoxType::String("hello you".to_string()));
+        assert_eq!(lax.eval("greet(\"bob\")").unwrap(),LoxType::String("hello bob".to_string()));
+        assert_eq!(lax.eval("half(3)").unwrap(),LoxType::Nil);
+        assert!(lax.eval("greet(1)").is_err());
+    }
+}
+
+#[test]
+fn errors_point_at_the_call() {
+    for mut lax in both() {
+        lax.register("hypot",hypot);
+        lax.register("fail",|| -> Result<f64,LoxError> { Err(LoxError::new("nope".to_string(),0)) });
+
+        let e = lax.eval("var x = 1;\nhypot(\"3\", 4);").unwrap_err();
+        assert_eq!(e.message(),"Argument 1 of 'hypot' must be a number but got string");
+        assert_eq!(e.line(),2);
+
+        let e = lax.eval("\n\nhypot(3);").unwrap_err();
+        assert_eq!(e.message(),"Expected 2 arguments but got 1");
+        assert_eq!(e.line(),3);
+
+        let e = lax.eval("fun f() {\n  return fail();\n}\nf();").unwrap_err();
+        assert_eq!(e.message(),"nope");
+        assert_eq!(e.line(),2);
+        assert_eq!(e.backtrace()[0].function,"fail");
+        assert_eq!(e.backtrace()[1].function,"f");
+    }
+}
+
+#[test]
+fn host_calls_reach_natives() {
+    let mut lax = Lax::new();
+    lax.register("hypot",hypot);
+    let v = lax.call_function("hypot",vec![LoxType::Number(6.0),LoxType::Number(8.0)]).unwrap();
+    assert_eq!(v,LoxType::Number(10.0));
+    assert!(lax.call_function("hypot",vec![LoxType::Nil,LoxType::Number(8.0)]).is_err());
+}
+
+#[test]
+fn trailing_options_and_rest_change_the_arity() {
+    for mut lax in both() {
+        lax.register("greet",|name: String,greeting: Option<String>| format!("{} {}",greeting.unwrap_or("hello".to_string()),name));
+        lax.register("sum",|first: f64,rest: Rest<f64>| rest.0.iter().fold(first,|a,b| a + b));
+
+        assert_eq!(lax.eval("greet(\"bob\")").unwrap(),LoxType::String("hello bob".to_string()));
+        assert_eq!(lax.eval("greet(\"bob\", \"hi\")").unwrap(),LoxType::String("hi bob".to_string()));
+        assert_eq!(lax.eval("sum(1)").unwrap(),LoxType::Number(1.0));
+        assert_eq!(lax.eval("sum(1, 2, 3, 4)").unwrap(),LoxType::Number(10.0));
+        assert_eq!(lax.eval("max(3, 9, 4)").unwrap(),LoxType::Number(9.0));
+        assert_eq!(lax.eval("min(3, 9, 4)").unwrap(),LoxType::Number(3.0));
+
+        assert_eq!(lax.eval("greet()").unwrap_err().message(),"Expected 1 to 2 arguments but got 0");
+        assert_eq!(lax.eval("sum()").unwrap_err().message(),"Expected at least 1 argument but got 0");
+        assert_eq!(lax.eval("len(1, 2)").unwrap_err().message(),"Expected 1 argument but got 2");
+        assert_eq!(lax.eval("sum(1, 2, \"3\")").unwrap_err().message(),"Argument 3 of 'sum' must be a number but got string");
+    }
+}
@@ -0,0 +1,86 @@
+extern crate lax;
+
+use lax::{ErrorKind,Expr,Statement};
+use lax::embed::parse;
+
+fn expression(source: &str) -> Expr {
+    let ast = parse(source).unwrap();
+    match ast[0].as_ref() {
+        &Statement::Expression(ref e) => e.clone(),
+        s => panic!("expected an expression statement, got {}",s)
+    }
+}
+
+#[test]
+fn postfix_operators_chain_left_to_right() {
+    let cases = vec![
+        ("f(1)(2);","var(f)(1)(2)"),
+        ("get()()[0]().k(1, 2);","var(get)()()[0]().k(1, 2)"),
+        ("a.b[c.d](e)[0].f;","var(a).b[var(c).d](var(e))[0].f"),
+        ("[(x) => x][0](1);","[<fn <anonymous>>][0](1)"),
+        ("{\"k\": f}[\"k\"]()();","{k: var(f)}[k]()()"),
+        ("(f)(1);","(group var(f))(1)")
+    ];
+
+    for (source,printed) in cases {
+        assert_eq!(format!("{}",expression(source)),printed,"{}",source);
+    }
+}
+
+#[test]
+fn assignment_targets_can_end_a_chain() {
+    match expression("a.b(1)[2].c = 3;") {
+        Expr::Set(ref object,ref name,_) => {
+            assert_eq!(name.lexeme,"c");
+            assert_eq!(format!("{}",object),"var(a).b(1)[2]");
+        },
+        ref e => panic!("expected a property assignment, got {}",e)
+    }
+
+    match expression("f()()[0] = 1;") {
+        Expr::SetIndex(ref object,_,ref index,_) => assert_eq!(format!("{}{}",object,index),"var(f)()()0"),
+        ref e => panic!("expected an index assignment, got {}",e)
+    }
+
+    assert!(parse("f() = 1;").is_err());
+    assert!(parse("a.b() = 1;").is_err());
+}
+
+#[test]
+fn operator_chains_have_no_length_limit() {
+    let sources = vec![
+        format!("1{};"," + 1".repeat(300)),
+        format!("true{};"," and true or false".repeat(300))
+    ];
+    for source in sources {
+        assert!(parse(&source).is_ok(),"{:.20}",source);
+    }
+}
+
+#[test]
+fn every_error_in_a_source_is_reported() {
+    let source = "var a = 1;@\nvar = 3;\nprint(a;\na + ;\n\"oops";
+    let errors = parse(source).unwrap_err();
+    let found: Vec<(ErrorKind,i32,&str)> = errors.iter().map(|e| (e.kind(),e.line(),e.message())).collect();
+    assert_eq!(found,vec![
+        (ErrorKind::Lexical,1,"Unexpected character"),
+        (ErrorKind::Lexical,5,"Unterminated string"),
+        (ErrorKind::Syntax,2,"Expected variable name"),
+        (ErrorKind::Syntax,3,"Expected ')' after arguments"),
+        (ErrorKind::Syntax,4,"Expected expression")
+    ]);
+}
+
+#[test]
+fn arrows_and_groupings_tell_apart() {
+    let cases = vec![
+        ("((x) => x)(1);","(group <fn <anonymous>>)(1)"),
+        ("(a) + (b);","(+ (group var(a)) (group var(b)))"),
+        ("f((x) => (x), (y));","var(f)(<fn <anonymous>>, (group var(y)))"),
+        ("(a = (b) => b) => a;","<fn <anonymous>>")
+    ];
+
+    for (source,printed) in cases {
+        assert_eq!(format!("{}",expression(source)),printed,"{}",source);
+    }
+}
@@ -0,0 +1,123 @@
+extern crate lax;
+
+mod common;
+
+use lax::{lox_std,LoxType};
+use lax::interpreter::Interpreter;
+use lax::repl::{self,Repl};
+use common::Capture;
+use std::env;
+use std::fs;
+use std::io::{Cursor,Write};
+use std::path::PathBuf;
+use std::process::{Command,Stdio};
+
+#[test]
+fn open_delimiters_continue_the_entry() {
+    assert!(repl::incomplete("fun f(x) {"));
+    assert!(repl::incomplete("print(1 +"));
+    assert!(repl::incomplete("var s = \"two\nlines"));
+    assert!(repl::incomplete("if (true) {\n  print(1);\n"));
+    assert!(repl::incomplete("print(\"${[1,"));
+    assert!(repl::incomplete("print(\"${x} and ${"));
+
+    assert!(!repl::incomplete("print(1);"));
+    assert!(!repl::incomplete("fun f(x) {\n  return x;\n}"));
+    assert!(!repl::incomplete("var s = \"(\";"));
+    assert!(!repl::incomplete(")"));
+    assert!(!repl::incomplete("print(\"${ {\"a\": 1} }\");"));
+}
+
+#[test]
+fn errors_leave_the_session_usable() {
+    for &vm in &[false,true] {
+        let mut repl = Repl::new(Interpreter::new(),vm);
+        assert!(repl.eval("var a = 1"));
+        assert!(!repl.eval("a + missing;"));
+        assert!(!repl.eval("fun broken( {"));
+        assert!(repl.eval("fun twice(x) { return x * 2; }"));
+        assert!(repl.eval("a = twice(a + 1);"));
+        assert!(repl.eval("a"));
+    }
+}
+
+#[test]
+fn top_level_definitions_persist() {
+    let mut i = Interpreter::new();
+    assert!(repl::run_source("var greeting = \"hi\";","<test>",&mut i,false));
+    assert_eq!(i.env.borrow().get("greeting"),Some(LoxType::String("hi".to_string())));
+}
+
+// A REPL reading `input` the way the binary reads stdin, with print going to the capture
+fn session(input: &str,vm: bool) -> Capture {
+    let output = Capture::new();
+    let mut i = Interpreter::new();
+    lox_std::install(&mut i.builtins.borrow_mut());
+    i.set_input(Cursor::new(input.to_string().into_bytes()));
+    i.set_output(output.clone());
+    Repl::new(i,vm).run();
+    output
+}
+
+#[test]
+fn readline_reads_the_next_line_of_the_session() {
+    for &vm in &[false,true] {
+        let output = session("var name = readline();\nworld\nprint(\"hello \" + name);\n",vm);
+        assert_eq!(output.text(),"> > hello world\n\n> \n");
+    }
+}
+
+#[test]
+fn everything_the_session_shows_goes_to_the_interpreters_output() {
+    for &vm in &[false,true] {
+        let output = session("var answer = 42;\nanswer\n:env\n:history\n",vm);
+        let lines = output.lines();
+        assert!(lines.iter().any(|l| l.ends_with("> 42")),"{:?}",lines);
+        assert!(lines.iter().any(|l| l.ends_with("answer = 42")),"{:?}",lines);
+        assert!(lines.iter().any(|l| l.ends_with("   2  answer")),"{:?}",lines);
+    }
+}
+
+// The binary built alongside the tests
+fn lax_binary() -> PathBuf {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join(format!("lax{}",env::consts::EXE_SUFFIX))
+}
+
+#[test]
+fn piped_sessions_can_call_readline() {
+    // The REPL writes its history to the working directory
+    let dir = env::temp_dir().join("lax_repl_piped_session");
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut lax = Command::new(lax_binary())
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    lax.stdin.take().unwrap().write_all(b"var x = readline();\npiped\nprint(x + \"!\");\n").unwrap();
+    let output = lax.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("piped\n!"));
+}
+
+#[test]
+fn history_entries_can_be_run_again() {
+    for &vm in &[false,true] {
+        let output = session("var a = 1;\na = a + 1;\n:history 2\na\n:history\n:history 9\n",vm);
+        let lines = output.lines();
+        assert!(lines.iter().any(|l| l.ends_with("> 3")),"{:?}",lines);
+
+        // The entry that ran is recorded, not the :history that recalled it
+        let listed = lines.iter().filter(|l| l.contains("  a = a + 1;")).count();
+        assert_eq!(listed,2,"{:?}",lines);
+        assert!(!lines.iter().any(|l| l.contains("  :history")),"{:?}",lines);
+    }
+}
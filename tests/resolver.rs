@@ -0,0 +1,63 @@
+extern crate lax;
+
+use lax::ErrorKind;
+use lax::embed::parse;
+
+// The single resolution error a source should get, with the line it's reported on
+fn check_errors(cases: &[(&str,&str,i32)]) {
+    for &(source,message,line) in cases {
+        let errors = parse(source).unwrap_err();
+        assert_eq!(errors.len(),1,"{}",source);
+        assert_eq!(errors[0].kind(),ErrorKind::Resolution,"{}",source);
+        assert_eq!((errors[0].message(),errors[0].line()),(message,line),"{}",source);
+    }
+}
+
+#[test]
+fn local_in_its_own_initializer() {
+    check_errors(&[
+        ("{\n  var a = a;\n}","Can't read local variable in its own initializer",2),
+        ("fun f() {\n  var b = 1 + b;\n}","Can't read local variable in its own initializer",2),
+        ("var a = 1;\n{\n  var a = a + 1;\n}","Can't read local variable in its own initializer",3)
+    ]);
+
+    // Globals are looked up when the code runs, so these are fine
+    assert!(parse("var a = a;").is_ok());
+    assert!(parse("var a = 1;\n{\n  var b = a;\n}").is_ok());
+}
+
+#[test]
+fn top_level_return() {
+    check_errors(&[
+        ("return;","Can't return from top-level code",1),
+        ("if (true) {\n  return 1;\n}","Can't return from top-level code",2)
+    ]);
+
+    assert!(parse("fun f() { return 1; }").is_ok());
+    assert!(parse("fun f() { { return; } }").is_ok());
+}
+
+#[test]
+fn redeclaration_in_the_same_scope() {
+    check_errors(&[
+        ("{\n  var a = 1;\n  var a = 2;\n}","Already a variable with this name in this scope",3),
+        ("fun f(a) {\n  var a;\n}","Already a variable with this name in this scope",2),
+        ("fun f(a, a) {}","Already a variable with this name in this scope",1)
+    ]);
+
+    // Globals can be redeclared, and an inner scope can shadow an outer one
+    assert!(parse("var a = 1;\nvar a = 2;").is_ok());
+    assert!(parse("{\n  var a = 1;\n  {\n    var a = 2;\n  }\n}").is_ok());
+}
+
+#[test]
+fn init_cant_return_a_value() {
+    check_errors(&[
+        ("class A {\n  init() {\n    return 1;\n  }\n}","Can't return a value from an initializer",3),
+        ("class A {\n  init() {\n    if (true) return this;\n  }\n}","Can't return a value from an initializer",3)
+    ]);
+
+    // A bare return is fine, and other methods can return whatever they like
+    assert!(parse("class A { init() { return; } }").is_ok());
+    assert!(parse("class A { m() { return 1; } }").is_ok());
+}